@@ -0,0 +1,275 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    ext::IdentExt, meta::ParseNestedMeta, parse_macro_input, parse_quote, Attribute, Data,
+    DeriveInput, Error, Fields, LitStr,
+};
+
+/// Derive `awlyc::Schema` for a struct with named fields
+/// The schema has the struct's name and one field per struct field. The serde attributes that
+/// change what serde deserializes are taken into account so the schema matches it, the ones the
+/// schema can't describe are rejected
+#[proc_macro_derive(Schema, attributes(serde))]
+pub fn derive_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match schema_impl(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn schema_impl(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "`Schema` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "`Schema` can only be derived for structs",
+            ))
+        }
+    };
+
+    let container = ContainerAttrs::parse(&input.attrs)?;
+    let mut entries = vec![];
+    for field in fields {
+        let attrs = FieldAttrs::parse(&field.attrs)?;
+        if attrs.skip {
+            continue;
+        }
+        let ty = &field.ty;
+        let schema = match attrs.deserialize_with {
+            true => quote!(::awlyc::Ty::Unknown),
+            false => quote!(<#ty as ::awlyc::Schema>::schema()),
+        };
+        if attrs.flatten {
+            entries.push(quote! {
+                ::awlyc::schema::SchemaField::Flatten(#schema)
+            });
+            continue;
+        }
+        let ident = field.ident.as_ref().unwrap().unraw().to_string();
+        let name = match (attrs.rename, &container.rename_all) {
+            (Some(name), _) => name,
+            (None, Some(rule)) => rule.apply(&ident),
+            (None, None) => ident,
+        };
+        let default = attrs.default || container.default;
+        entries.push(quote! {
+            ::awlyc::schema::SchemaField::Field(#name, #schema, #default)
+        });
+    }
+
+    let ident = &input.ident;
+    let name = container.rename.unwrap_or_else(|| ident.to_string());
+    let deny_unknown_fields = container.deny_unknown_fields;
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::awlyc::Schema));
+    }
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::awlyc::Schema for #ident #ty_generics #where_clause {
+            fn schema() -> ::awlyc::Ty {
+                ::awlyc::schema::named::<Self>(
+                    #name,
+                    #deny_unknown_fields,
+                    || ::std::vec![#(#entries),*],
+                )
+            }
+        }
+    })
+}
+
+/// The serde container attributes that change the shape of the deserialized value
+#[derive(Default)]
+struct ContainerAttrs {
+    rename: Option<String>,
+    rename_all: Option<RenameRule>,
+    default: bool,
+    deny_unknown_fields: bool,
+}
+
+impl ContainerAttrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut parsed = ContainerAttrs::default();
+        parse_serde_attrs(attrs, |meta| {
+            if meta.path.is_ident("rename") {
+                parsed.rename = deserialize_name(&meta)?.map(|name| name.value());
+            } else if meta.path.is_ident("rename_all") {
+                if let Some(rule) = deserialize_name(&meta)? {
+                    parsed.rename_all = Some(RenameRule::parse(&rule)?);
+                }
+            } else if meta.path.is_ident("default") {
+                parsed.default = true;
+                skip_value(&meta)?;
+            } else if meta.path.is_ident("deny_unknown_fields") {
+                parsed.deny_unknown_fields = true;
+            } else if ["transparent", "from", "try_from", "tag"]
+                .iter()
+                .any(|name| meta.path.is_ident(name))
+            {
+                return Err(unsupported(&meta));
+            } else {
+                skip_value(&meta)?;
+            }
+            Ok(())
+        })?;
+        Ok(parsed)
+    }
+}
+
+/// The serde field attributes that change the shape of the deserialized value
+#[derive(Default)]
+struct FieldAttrs {
+    rename: Option<String>,
+    skip: bool,
+    default: bool,
+    flatten: bool,
+    /// The field accepts whatever its function does, so it's `any`
+    deserialize_with: bool,
+}
+
+impl FieldAttrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut parsed = FieldAttrs::default();
+        parse_serde_attrs(attrs, |meta| {
+            if meta.path.is_ident("rename") {
+                parsed.rename = deserialize_name(&meta)?.map(|name| name.value());
+            } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing") {
+                parsed.skip = true;
+            } else if meta.path.is_ident("default") {
+                parsed.default = true;
+                skip_value(&meta)?;
+            } else if meta.path.is_ident("flatten") {
+                parsed.flatten = true;
+            } else if meta.path.is_ident("with") || meta.path.is_ident("deserialize_with") {
+                parsed.deserialize_with = true;
+                skip_value(&meta)?;
+            } else if meta.path.is_ident("alias") {
+                return Err(unsupported(&meta));
+            } else {
+                skip_value(&meta)?;
+            }
+            Ok(())
+        })?;
+        Ok(parsed)
+    }
+}
+
+/// Call `f` with every item of the `#[serde(...)]` attributes in `attrs`
+fn parse_serde_attrs(
+    attrs: &[Attribute],
+    mut f: impl FnMut(ParseNestedMeta) -> syn::Result<()>,
+) -> syn::Result<()> {
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(&mut f)?;
+    }
+    Ok(())
+}
+
+/// Name given by `name = "..."` or `name(deserialize = "...")`, `None` if only the serialized
+/// name is given
+fn deserialize_name(meta: &ParseNestedMeta) -> syn::Result<Option<LitStr>> {
+    if !meta.input.peek(syn::token::Paren) {
+        return Ok(Some(meta.value()?.parse()?));
+    }
+    let mut name = None;
+    meta.parse_nested_meta(|inner| {
+        let value: LitStr = inner.value()?.parse()?;
+        if inner.path.is_ident("deserialize") {
+            name = Some(value);
+        }
+        Ok(())
+    })?;
+    Ok(name)
+}
+
+/// Skip the value of an attribute that doesn't matter here
+fn skip_value(meta: &ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        let content;
+        syn::parenthesized!(content in meta.input);
+        content.parse::<TokenStream2>()?;
+    }
+    Ok(())
+}
+
+fn unsupported(meta: &ParseNestedMeta) -> Error {
+    let name = meta.path.get_ident().unwrap();
+    meta.error(format!(
+        "`#[serde({})]` changes what is deserialized in a way `Schema` can't describe",
+        name
+    ))
+}
+
+/// Case a container's `rename_all` converts field names to
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(rule: &LitStr) -> syn::Result<Self> {
+        let parsed = match rule.value().as_str() {
+            "lowercase" => RenameRule::Lower,
+            "UPPERCASE" => RenameRule::Upper,
+            "PascalCase" => RenameRule::Pascal,
+            "camelCase" => RenameRule::Camel,
+            "snake_case" => RenameRule::Snake,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnake,
+            "kebab-case" => RenameRule::Kebab,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebab,
+            other => {
+                return Err(Error::new_spanned(
+                    rule,
+                    format!("unknown rename rule `rename_all = {:?}`", other),
+                ))
+            }
+        };
+        Ok(parsed)
+    }
+
+    /// Name of a field written in snake case, the way serde renames it
+    fn apply(&self, field: &str) -> String {
+        match self {
+            RenameRule::Lower | RenameRule::Snake => field.to_string(),
+            RenameRule::Upper | RenameRule::ScreamingSnake => field.to_ascii_uppercase(),
+            RenameRule::Pascal => field
+                .split('_')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                        None => String::new(),
+                    }
+                })
+                .collect(),
+            RenameRule::Camel => {
+                let pascal = RenameRule::Pascal.apply(field);
+                let mut chars = pascal.chars();
+                match chars.next() {
+                    Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+                    None => pascal,
+                }
+            }
+            RenameRule::Kebab => field.replace('_', "-"),
+            RenameRule::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
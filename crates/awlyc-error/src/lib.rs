@@ -1,4 +1,4 @@
-use ariadne::{sources, Color, Label, Report, ReportKind};
+use ariadne::{sources, Color, Label, Report, ReportBuilder, ReportKind};
 use smol_str::SmolStr;
 use std::fmt;
 use text_size::TextRange;
@@ -25,12 +25,10 @@ pub struct Span {
 }
 
 impl Span {
-    /// Combine two spans in the same file
-    /// a must come before b
+    /// Combine two spans in the same file, the result covers both and everything in between
     pub fn combine(a: &Self, b: &Self) -> Self {
-        assert!(a.range.start() < b.range.end());
         Span {
-            range: TextRange::new(a.range.start(), b.range.end()),
+            range: a.range.cover(b.range),
             file_id: a.file_id.clone(),
         }
     }
@@ -78,50 +76,54 @@ pub struct Diagnostic {
     pub msg: String,
     /// Where the diagnostic occurs
     pub span: Span,
+    /// Secondary locations related to the diagnostic, e.g. where a duplicate key is first used
+    pub labels: Vec<(Span, String)>,
 }
 
 impl Diagnostic {
     pub fn to_report(&self) -> Report<Span> {
-        let report = Report::build(
-            ReportKind::Error,
-            self.span.file_id.clone(),
-            self.span.range.start().into(),
-        )
-        .with_message(self.msg.clone())
-        .with_label(
+        let mut report = self.report_builder().with_label(
             Label::new(self.span.clone())
                 .with_message(self.msg.clone())
                 .with_color(Color::Blue),
         );
+        report.add_labels(self.labels.iter().map(|(span, msg)| {
+            Label::new(span.clone())
+                .with_message(msg)
+                .with_color(Color::Yellow)
+        }));
         report.finish()
     }
+
+    /// Report with only the message, for diagnostics that aren't in any source, e.g. a file that
+    /// couldn't be opened
+    fn to_report_without_source(&self) -> Report<Span> {
+        self.report_builder().finish()
+    }
+
+    fn report_builder(&self) -> ReportBuilder<Span> {
+        let kind = match self.kind {
+            DiagnosticKind::Error => ReportKind::Error,
+            DiagnosticKind::Warning => ReportKind::Warning,
+            DiagnosticKind::Note => ReportKind::Advice,
+        };
+        Report::build(
+            kind,
+            self.span.file_id.clone(),
+            self.span.range.start().into(),
+        )
+        .with_message(self.msg.clone())
+    }
 }
 
 impl fmt::Display for Diagnostic {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "err")
+        write!(f, "{}", self.msg)
     }
 }
 
 impl std::error::Error for Diagnostic {}
 
-// TODO: huh?
-impl serde::de::Error for Diagnostic {
-    fn custom<T>(msg: T) -> Self
-    where
-        T: fmt::Display,
-    {
-        Diagnostic {
-            kind: DiagnosticKind::Error,
-            msg: msg.to_string(),
-            span: Span {
-                range: TextRange::new(0.into(), 0.into()),
-                file_id: FileId(SmolStr::from("err")),
-            },
-        }
-    }
-}
-
 pub struct DiagnosticReporter {
     pub files: Vec<(FileId, String)>,
 }
@@ -133,6 +135,10 @@ impl DiagnosticReporter {
     }
 
     pub fn report(&self, err: &Diagnostic) {
-        err.to_report().print(sources(self.files.clone())).unwrap();
+        let report = match self.files.iter().any(|(id, _)| *id == err.span.file_id) {
+            true => err.to_report(),
+            false => err.to_report_without_source(),
+        };
+        report.print(sources(self.files.clone())).unwrap();
     }
 }
@@ -0,0 +1,810 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use awlyc_error::{Diagnostic, DiagnosticKind, FileId, Span};
+use awlyc_parser::{
+    ast::{Call, Expr, ExprIdx, FnDecl, ImportDecl, RecordEntry, Spanned, TypeExpr},
+    Module,
+};
+use itertools::Itertools;
+use la_arena::{Arena, ArenaMap};
+use smol_str::SmolStr;
+use text_size::TextRange;
+
+pub mod ty;
+
+use ty::Ty;
+
+/// Function, identified by the module it's declared in and its position in that module
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FnId {
+    pub module: FileId,
+    pub idx: usize,
+}
+
+/// Schema, identified by the module it's declared in and its position in that module
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SchemaId {
+    pub module: FileId,
+    pub idx: usize,
+}
+
+/// What a path expression refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Parameter of the enclosing function, by position
+    Param(usize),
+    /// Function being called, paths only refer to functions as the callee of a call
+    Function(FnId),
+}
+
+/// Resolution of every path expression, keyed by the path's expression index
+pub type Resolutions = ArenaMap<ExprIdx, Resolution>;
+
+/// Where the value of a parameter comes from in a call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Argument {
+    /// Argument at this position in the call, evaluated where the call is
+    Passed(usize),
+    /// Element of the array spread into the call at this position, `f(...list)`
+    Element(usize, usize),
+    /// Default value of the parameter, evaluated where the function is declared
+    Default(ExprIdx),
+    /// Positional arguments left after the other parameters, collected into an array for a
+    /// variadic parameter
+    Rest(Vec<Argument>),
+    /// Before evaluation, the argument can come from an array spread into the call
+    Unknown,
+}
+
+/// FileId of a module on disk, this is its canonical path
+/// Errors if the path isn't valid UTF-8, as FileIds are strings
+pub fn file_id(path: &Path) -> Result<FileId, Diagnostic> {
+    match path.to_str() {
+        Some(path) => Ok(FileId(SmolStr::from(path))),
+        None => Err(Diagnostic {
+            kind: DiagnosticKind::Error,
+            msg: format!("path `{}` is not valid UTF-8", path.display()),
+            span: Span {
+                range: TextRange::new(0.into(), 0.into()),
+                file_id: FileId(SmolStr::from("")),
+            },
+            labels: vec![],
+        }),
+    }
+}
+
+/// Path of the file an import refers to
+/// Imports are relative to the file of the importing module, or to the working directory if the
+/// importing module isn't backed by a file (the repl for example)
+pub fn resolve_import(importer: &FileId, import: &ImportDecl) -> PathBuf {
+    let importer = Path::new(importer.0.as_str());
+    let path = match importer.parent() {
+        Some(dir) if importer.is_absolute() => dir.join(import.path.as_str()),
+        _ => PathBuf::from(import.path.as_str()),
+    };
+    fs::canonicalize(&path).unwrap_or(path)
+}
+
+/// Schema a type name refers to in the scope of a module, e.g. `Project` or `alias.Project`
+pub fn find_schema(
+    modules: &HashMap<FileId, Module>,
+    module_id: &FileId,
+    path: &[SmolStr],
+) -> Option<SchemaId> {
+    let module = &modules[module_id];
+    let (module, name) = match path {
+        [name] => (module, name),
+        [alias, name] => {
+            let import = module.imports.iter().find(|i| &i.name.inner == alias)?;
+            let imported = file_id(&resolve_import(&module.file_id, import)).ok()?;
+            (modules.get(&imported)?, name)
+        }
+        _ => return None,
+    };
+    let idx = module.schemas.iter().position(|s| &s.name.inner == name)?;
+    Some(SchemaId {
+        module: module.file_id.clone(),
+        idx,
+    })
+}
+
+/// Binds every path expression to what it refers to, before anything is evaluated
+/// This is where unknown names and calls with the wrong number of arguments are caught, in all
+/// functions and not only those that end up being called
+pub struct Resolver<'a> {
+    modules: &'a HashMap<FileId, Module>,
+    expr_arena: &'a Arena<Spanned<Expr>>,
+    resolutions: Resolutions,
+    diagnostics: Vec<Diagnostic>,
+    /// Import aliases that have been referenced, per module
+    used_imports: HashSet<(FileId, SmolStr)>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(modules: &'a HashMap<FileId, Module>, expr_arena: &'a Arena<Spanned<Expr>>) -> Self {
+        Self {
+            modules,
+            expr_arena,
+            resolutions: Resolutions::default(),
+            diagnostics: vec![],
+            used_imports: HashSet::new(),
+        }
+    }
+
+    pub fn finish(self) -> (Resolutions, Vec<Diagnostic>) {
+        (self.resolutions, self.diagnostics)
+    }
+
+    fn error(&mut self, msg: String, span: Span) {
+        self.diagnostics.push(Diagnostic {
+            kind: DiagnosticKind::Error,
+            msg,
+            span,
+            labels: vec![],
+        });
+    }
+
+    /// Resolve every function and expression of every module
+    pub fn resolve_modules(&mut self) {
+        // sorted so diagnostics come out in the same order every time
+        let modules = self.modules;
+        for (_, module) in modules.iter().sorted_by(|(a, _), (b, _)| a.0.cmp(&b.0)) {
+            for function in &module.functions {
+                let annotations = function.params.0.iter().filter_map(|p| p.ty.as_ref());
+                for ty in annotations.chain(&function.ret_ty) {
+                    self.resolve_type(module, ty);
+                }
+                for default in function.params.0.iter().filter_map(|p| p.default) {
+                    self.resolve_expr_in(module, None, default);
+                }
+                self.resolve_expr_in(module, Some(function), function.body);
+            }
+            for (idx, schema) in module.schemas.iter().enumerate() {
+                for (_, ty) in &schema.fields {
+                    self.resolve_type(module, ty);
+                }
+                let id = SchemaId {
+                    module: module.file_id.clone(),
+                    idx,
+                };
+                if self.refers_to(&id, &id, &mut vec![]) {
+                    self.error(
+                        format!("recursive schema `{}`", schema.name.inner),
+                        schema.name.span.clone(),
+                    );
+                }
+            }
+            if let Some(expr) = module.expr {
+                self.resolve_expr_in(module, None, expr);
+            }
+            self.check_imports(module);
+        }
+    }
+
+    /// Resolve an expression that isn't part of a module in the scope of that module
+    /// This is how the repl evaluates its input
+    pub fn resolve_expr(&mut self, module_id: &FileId, expr: ExprIdx) {
+        let module = &self.modules[module_id];
+        self.resolve_expr_in(module, None, expr);
+    }
+
+    /// Resolve the function a path refers to in the scope of a module, e.g. `f` or `alias.f`
+    pub fn resolve_fn(&mut self, module_id: &FileId, path: &[Spanned<SmolStr>]) -> Option<FnId> {
+        let module = &self.modules[module_id];
+        match path {
+            [name] => self.find_function(module, name),
+            // import foo "path.awlyc" -- foo is the import alias
+            [alias, name] => {
+                let import = module.imports.iter().find(|i| i.name.inner == alias.inner);
+                match import {
+                    Some(import) => {
+                        self.used_imports
+                            .insert((module.file_id.clone(), alias.inner.clone()));
+                        let imported = file_id(&resolve_import(&module.file_id, import));
+                        // missing modules are reported with the import
+                        match imported.ok().and_then(|id| self.modules.get(&id)) {
+                            Some(imported) => self.find_function(imported, name),
+                            None => None,
+                        }
+                    }
+                    None => {
+                        self.error(
+                            format!("unknown module referenced `{}`", alias.inner),
+                            alias.span.clone(),
+                        );
+                        None
+                    }
+                }
+            }
+            _ => {
+                self.error(
+                    format!(
+                        "unknown function referenced `{}`",
+                        path.iter().map(|s| s.inner.as_str()).join(".")
+                    ),
+                    Span::combine(&path[0].span, &path[path.len() - 1].span),
+                );
+                None
+            }
+        }
+    }
+
+    fn resolve_type(&mut self, module: &Module, ty: &Spanned<TypeExpr>) {
+        match &ty.inner {
+            TypeExpr::Named(path) => {
+                if let [name] = path.as_slice() {
+                    if Ty::builtin(name).is_some() {
+                        return;
+                    }
+                }
+                if let [alias, _] = path.as_slice() {
+                    self.used_imports
+                        .insert((module.file_id.clone(), alias.clone()));
+                }
+                if find_schema(self.modules, &module.file_id, path).is_none() {
+                    self.error(
+                        format!("unknown type `{}`", path.join(".")),
+                        ty.span.clone(),
+                    );
+                }
+            }
+            TypeExpr::Array(el) | TypeExpr::Optional(el) => self.resolve_type(module, el),
+            TypeExpr::Record(fields) => {
+                for (_, field) in fields {
+                    self.resolve_type(module, field);
+                }
+            }
+            TypeExpr::Error => {}
+        }
+    }
+
+    /// Whether the required fields of schema `id` refer to `target`, directly or through other
+    /// schemas
+    /// A value of such a schema could never be finished, one that only refers to itself through
+    /// an array or an optional ends with `[]` or `null`
+    fn refers_to(&self, id: &SchemaId, target: &SchemaId, visited: &mut Vec<SchemaId>) -> bool {
+        if visited.contains(id) {
+            return false;
+        }
+        visited.push(id.clone());
+        let schema = &self.modules[&id.module].schemas[id.idx];
+        schema
+            .fields
+            .iter()
+            .any(|(_, ty)| self.type_refers_to(&id.module, &ty.inner, target, visited))
+    }
+
+    fn type_refers_to(
+        &self,
+        module_id: &FileId,
+        ty: &TypeExpr,
+        target: &SchemaId,
+        visited: &mut Vec<SchemaId>,
+    ) -> bool {
+        match ty {
+            TypeExpr::Named(path) => match find_schema(self.modules, module_id, path) {
+                Some(id) => &id == target || self.refers_to(&id, target, visited),
+                None => false,
+            },
+            TypeExpr::Array(_) | TypeExpr::Optional(_) => false,
+            TypeExpr::Record(fields) => fields
+                .iter()
+                .any(|(_, ty)| self.type_refers_to(module_id, &ty.inner, target, visited)),
+            TypeExpr::Error => false,
+        }
+    }
+
+    fn check_imports(&mut self, module: &Module) {
+        for import in &module.imports {
+            let imported = file_id(&resolve_import(&module.file_id, import));
+            if !imported.is_ok_and(|id| self.modules.contains_key(&id)) {
+                self.error(
+                    format!("unresolved import `{}`", import.path),
+                    import.name.span.clone(),
+                );
+            } else if !self
+                .used_imports
+                .contains(&(module.file_id.clone(), import.name.inner.clone()))
+            {
+                self.diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::Warning,
+                    msg: format!("unused import `{}`", import.name.inner),
+                    span: import.name.span.clone(),
+                    labels: vec![],
+                });
+            }
+        }
+    }
+
+    fn resolve_expr_in(&mut self, module: &'a Module, function: Option<&FnDecl>, idx: ExprIdx) {
+        let expr_arena = self.expr_arena;
+        match &expr_arena[idx].inner {
+            Expr::Path(path) => self.resolve_value_path(function, idx, path),
+            Expr::Array(els) => {
+                for el in els {
+                    self.resolve_expr_in(module, function, *el);
+                }
+            }
+            Expr::Record(record) => {
+                for entry in &record.entries {
+                    let (RecordEntry::Field(_, value) | RecordEntry::Spread(value)) = entry;
+                    self.resolve_expr_in(module, function, *value);
+                }
+            }
+            Expr::Binop(binop) => {
+                self.resolve_expr_in(module, function, binop.lhs);
+                self.resolve_expr_in(module, function, binop.rhs);
+            }
+            Expr::Negate(negate) => self.resolve_expr_in(module, function, negate.expr),
+            Expr::Field(field) => self.resolve_expr_in(module, function, field.expr),
+            Expr::Index(index) => {
+                self.resolve_expr_in(module, function, index.expr);
+                self.resolve_expr_in(module, function, index.index);
+            }
+            Expr::Call(call) => {
+                self.resolve_call(module, function, call);
+                for arg in &call.args.inner {
+                    self.resolve_expr_in(module, function, arg.value);
+                }
+            }
+            Expr::Int(_)
+            | Expr::Float(_)
+            | Expr::String(_)
+            | Expr::Bool(_)
+            | Expr::Null
+            | Expr::Error => {}
+        }
+    }
+
+    /// A path that is used as a value, the only values that can be named are parameters
+    fn resolve_value_path(
+        &mut self,
+        function: Option<&FnDecl>,
+        idx: ExprIdx,
+        path: &[Spanned<SmolStr>],
+    ) {
+        let param = match (function, path) {
+            (Some(function), [name]) => function
+                .params
+                .0
+                .iter()
+                .position(|p| p.name.inner == name.inner),
+            _ => None,
+        };
+        match param {
+            Some(param) => self.resolutions.insert(idx, Resolution::Param(param)),
+            None => self.error(
+                format!(
+                    "unknown identifier referenced `{}`",
+                    path.iter().map(|s| s.inner.as_str()).join(".")
+                ),
+                self.expr_arena[idx].span.clone(),
+            ),
+        }
+    }
+
+    fn resolve_call(&mut self, module: &'a Module, function: Option<&FnDecl>, call: &Call) {
+        let callee = &self.expr_arena[call.callee];
+        let path = match &callee.inner {
+            Expr::Path(path) => path,
+            _ => {
+                self.error(
+                    "expression is not callable".to_string(),
+                    callee.span.clone(),
+                );
+                return self.resolve_expr_in(module, function, call.callee);
+            }
+        };
+
+        let fn_id = self.resolve_fn(&module.file_id, path);
+
+        if let Some(fn_id) = fn_id {
+            let f = &self.modules[&fn_id.module].functions[fn_id.idx];
+            if let Err(errors) = bind_args(f, call, self.expr_arena, None) {
+                self.diagnostics.extend(errors);
+            }
+            self.resolutions
+                .insert(call.callee, Resolution::Function(fn_id));
+        }
+    }
+
+    fn find_function(&mut self, module: &Module, name: &Spanned<SmolStr>) -> Option<FnId> {
+        let idx = module
+            .functions
+            .iter()
+            .position(|f| f.name.inner == name.inner);
+        if idx.is_none() {
+            self.error(
+                format!("unknown function referenced `{}`", name.inner),
+                name.span.clone(),
+            );
+        }
+        idx.map(|idx| FnId {
+            module: module.file_id.clone(),
+            idx,
+        })
+    }
+}
+
+/// Argument of every parameter of `f` in `call`, in the order of the parameters
+/// Positional arguments are bound first, then named ones, parameters that are left get their
+/// default value. Arguments that don't bind to a parameter and parameters without one are errors
+/// `spread_lens` are the lengths of the arrays spread into the call, by the position of their
+/// argument. Before evaluation they aren't known, parameters that could get an element of one
+/// are bound to `Argument::Unknown` and only the errors that don't depend on them are reported
+pub fn bind_args(
+    f: &FnDecl,
+    call: &Call,
+    expr_arena: &Arena<Spanned<Expr>>,
+    spread_lens: Option<&[usize]>,
+) -> Result<Vec<Argument>, Vec<Diagnostic>> {
+    enum Slot {
+        Empty,
+        /// Bound to an argument, passed at the span
+        Bound(Argument, Span),
+        /// Could be bound to an element of an array spread into the call
+        Unknown,
+    }
+
+    let params = &f.params.0;
+    let mut errors = vec![];
+    let error = |msg: String, span: Span, labels| Diagnostic {
+        kind: DiagnosticKind::Error,
+        msg,
+        span,
+        labels,
+    };
+    let variadic = params.last().is_some_and(|p| p.variadic);
+    let fixed = &params[..params.len() - usize::from(variadic)];
+
+    // positional arguments up to the first spread of unknown length, and how many there are at
+    // least in total
+    let mut positional = vec![];
+    let mut unknown = false;
+    let mut found = 0;
+    for (idx, arg) in call.args.iter().enumerate() {
+        if arg.name.is_some() {
+            continue;
+        }
+        let args = match (arg.spread, spread_lens) {
+            (false, _) => vec![Argument::Passed(idx)],
+            (true, Some(lens)) => (0..lens[idx])
+                .map(|el| Argument::Element(idx, el))
+                .collect(),
+            (true, None) => {
+                unknown = true;
+                vec![]
+            }
+        };
+        found += args.len();
+        if !unknown {
+            let span = &expr_arena[arg.value].span;
+            positional.extend(args.into_iter().map(|arg| (arg, span.clone())));
+        }
+    }
+    let named: Vec<_> = call
+        .args
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, arg)| Some((idx, arg.name.as_ref()?)))
+        .collect();
+
+    let required = fixed.iter().filter(|p| p.default.is_none()).count();
+    let too_many = !variadic && found > fixed.len();
+    // without names, the count alone says what's wrong
+    let too_few = !unknown && named.is_empty() && found < required;
+    if too_many || too_few {
+        let expected = if required == fixed.len() && !variadic {
+            required.to_string()
+        } else if too_few {
+            format!("at least {}", required)
+        } else {
+            format!("at most {}", fixed.len())
+        };
+        errors.push(error(
+            format!(
+                "incorrect number of arguments supplied to `{}`: expected {}, found {}",
+                f.name.inner, expected, found
+            ),
+            call.args.span.clone(),
+            vec![],
+        ));
+    }
+
+    let mut slots: Vec<_> = (0..fixed.len())
+        .map(|idx| match positional.get(idx) {
+            Some((arg, span)) => Slot::Bound(arg.clone(), span.clone()),
+            None if unknown => Slot::Unknown,
+            None => Slot::Empty,
+        })
+        .collect();
+    for (idx, name) in named {
+        let param = params.iter().position(|p| p.name.inner == name.inner);
+        match param.map(|param| slots.get_mut(param)) {
+            None => {
+                let names = params.iter().map(|p| &p.name.inner);
+                let labels = match similar_name(&name.inner, names) {
+                    Some(similar) => {
+                        vec![(name.span.clone(), format!("did you mean `{}`?", similar))]
+                    }
+                    None => vec![],
+                };
+                errors.push(error(
+                    format!(
+                        "unknown argument `{}` supplied to `{}`",
+                        name.inner, f.name.inner
+                    ),
+                    name.span.clone(),
+                    labels,
+                ));
+            }
+            // the variadic parameter has no slot
+            Some(None) => errors.push(error(
+                format!(
+                    "variadic parameter `{}` can't be passed by name",
+                    name.inner
+                ),
+                name.span.clone(),
+                vec![],
+            )),
+            Some(Some(Slot::Bound(_, first))) => errors.push(error(
+                format!(
+                    "argument `{}` supplied to `{}` more than once",
+                    name.inner, f.name.inner
+                ),
+                name.span.clone(),
+                vec![(
+                    first.clone(),
+                    format!("`{}` first supplied here", name.inner),
+                )],
+            )),
+            Some(Some(slot)) => *slot = Slot::Bound(Argument::Passed(idx), name.span.clone()),
+        }
+    }
+
+    let mut args = vec![];
+    for (param, slot) in fixed.iter().zip(slots) {
+        match (slot, param.default) {
+            (Slot::Bound(arg, _), _) => args.push(arg),
+            (Slot::Unknown, _) => args.push(Argument::Unknown),
+            (Slot::Empty, Some(default)) => args.push(Argument::Default(default)),
+            (Slot::Empty, None) if too_few => {}
+            (Slot::Empty, None) => errors.push(error(
+                format!(
+                    "missing argument `{}` in call to `{}`",
+                    param.name.inner, f.name.inner
+                ),
+                call.args.span.clone(),
+                vec![],
+            )),
+        }
+    }
+    if variadic && unknown {
+        args.push(Argument::Unknown);
+    } else if variadic {
+        let rest = positional.into_iter().skip(fixed.len());
+        args.push(Argument::Rest(rest.map(|(arg, _)| arg).collect()));
+    }
+    if errors.is_empty() {
+        Ok(args)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Name among `candidates` that `name` is most likely a misspelling of
+pub fn similar_name<'c>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'c SmolStr>,
+) -> Option<&'c SmolStr> {
+    // about one edit every three characters
+    let max_distance = name.chars().count().max(3) / 3;
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::damerau_levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Resolve all modules
+pub fn resolve(
+    modules: &HashMap<FileId, Module>,
+    expr_arena: &Arena<Spanned<Expr>>,
+) -> (Resolutions, Vec<Diagnostic>) {
+    let mut resolver = Resolver::new(modules, expr_arena);
+    resolver.resolve_modules();
+    resolver.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use awlyc_error::{Diagnostic, DiagnosticKind, FileId};
+    use awlyc_parser::parse;
+    use la_arena::Arena;
+    use smol_str::SmolStr;
+
+    use crate::resolve;
+
+    fn resolve_srcs(srcs: &[(&str, &str)]) -> Vec<Diagnostic> {
+        let mut expr_arena = Arena::default();
+        let mut modules = HashMap::new();
+        for (name, src) in srcs {
+            let file_id = FileId(SmolStr::from(*name));
+            let (module, errors) = parse(src, &mut expr_arena, file_id.clone());
+            assert!(errors.is_empty(), "{:#?}", errors);
+            modules.insert(file_id, module);
+        }
+        resolve(&modules, &expr_arena).1
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics.iter().map(|d| d.msg.as_str()).collect()
+    }
+
+    #[test]
+    fn unresolved_names_in_uncalled_functions() {
+        let diagnostics = resolve_srcs(&[(
+            "main",
+            "fn unused(a) { [a, b, missing(a)] } fn f(x) { x } f(y)",
+        )]);
+        assert_eq!(
+            messages(&diagnostics),
+            [
+                "unknown identifier referenced `b`",
+                "unknown function referenced `missing`",
+                "unknown identifier referenced `y`",
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_arity() {
+        let diagnostics = resolve_srcs(&[("main", "fn f(a, b) { a } f(1)")]);
+        assert_eq!(
+            messages(&diagnostics),
+            ["incorrect number of arguments supplied to `f`: expected 2, found 1"]
+        );
+    }
+
+    #[test]
+    fn named_arguments() {
+        let diagnostics = resolve_srcs(&[(
+            "main",
+            "fn f(name, repo, docs = null) { name } fn g(x = 1) { x }
+[f(1, 2), f(repo: 1, name: 2), g(), f(1), f(1, 2, 3, 4), f(repo: 1), f(1, nmae: 2), f(1, name: 2), f(1, repo: 2, repo: 3)]",
+        )]);
+        assert_eq!(
+            messages(&diagnostics),
+            [
+                "incorrect number of arguments supplied to `f`: expected at least 2, found 1",
+                "incorrect number of arguments supplied to `f`: expected at most 3, found 4",
+                "missing argument `name` in call to `f`",
+                "unknown argument `nmae` supplied to `f`",
+                "missing argument `repo` in call to `f`",
+                "argument `name` supplied to `f` more than once",
+                "missing argument `repo` in call to `f`",
+                "argument `repo` supplied to `f` more than once",
+            ]
+        );
+        assert_eq!(diagnostics[3].labels[0].1, "did you mean `name`?");
+        assert_eq!(diagnostics[5].labels[0].1, "`name` first supplied here");
+    }
+
+    #[test]
+    fn variadic_arguments() {
+        let diagnostics = resolve_srcs(&[(
+            "main",
+            "fn f(name, ...rest) { name } fn g(x, y) { x }
+[f(1), f(1, 2, 3), f(...[1]), g(...[1, 2]), g(1, ...[2]), f(), f(1, rest: 2), g(1, 2, ...[3])]",
+        )]);
+        assert_eq!(
+            messages(&diagnostics),
+            [
+                "incorrect number of arguments supplied to `f`: expected at least 1, found 0",
+                "variadic parameter `rest` can't be passed by name",
+            ]
+        );
+    }
+
+    #[test]
+    fn imports() {
+        let diagnostics = resolve_srcs(&[
+            (
+                "main",
+                r#"import used "lib" import unused "lib" import gone "nope" [used.f(1), used.g()]"#,
+            ),
+            ("lib", "fn f(x) { x }"),
+        ]);
+        assert_eq!(
+            messages(&diagnostics),
+            [
+                "unknown function referenced `g`",
+                "unused import `unused`",
+                "unresolved import `nope`",
+            ]
+        );
+        assert_eq!(diagnostics[1].kind, DiagnosticKind::Warning);
+    }
+
+    #[test]
+    fn postfix_chains() {
+        // only functions can be called and they aren't values
+        let diagnostics = resolve_srcs(&[
+            (
+                "main",
+                r#"import lib "lib" [lib.f(1).a[0], lib.f, f()(), g(1).x()]"#,
+            ),
+            ("lib", "fn f(x) { x }"),
+        ]);
+        assert_eq!(
+            messages(&diagnostics),
+            [
+                "unknown identifier referenced `lib`",
+                "expression is not callable",
+                "unknown function referenced `f`",
+                "expression is not callable",
+                "unknown function referenced `g`",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_types() {
+        let diagnostics =
+            resolve_srcs(&[("main", "fn f(a: int, b: [Topic]) -> { name: strnig } { a }")]);
+        assert_eq!(
+            messages(&diagnostics),
+            ["unknown type `Topic`", "unknown type `strnig`"]
+        );
+    }
+
+    #[test]
+    fn schemas() {
+        let diagnostics = resolve_srcs(&[
+            (
+                "main",
+                r#"import lib "lib" schema Page { links: [Link], parent: Page } fn f(p: lib.Topic) -> Page { p }"#,
+            ),
+            ("lib", "schema Topic { name: string }"),
+        ]);
+        assert_eq!(
+            messages(&diagnostics),
+            ["unknown type `Link`", "recursive schema `Page`"]
+        );
+
+        // arrays and optionals end the recursion, through other schemas too
+        let diagnostics = resolve_srcs(&[(
+            "main",
+            "schema Tree { children: [Tree] } schema Page { parent: Page?, meta: { up: Page? } }
+            schema A { b: B } schema B { a: A? }
+            schema C { d: { c: C } }
+            null",
+        )]);
+        assert_eq!(messages(&diagnostics), ["recursive schema `C`"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_id() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+        use crate::file_id;
+
+        let path = Path::new(OsStr::from_bytes(b"/tmp/\xff.awlyc"));
+        assert_eq!(
+            file_id(path).unwrap_err().msg,
+            "path `/tmp/\u{FFFD}.awlyc` is not valid UTF-8"
+        );
+        assert_eq!(
+            file_id(Path::new("/tmp/a.awlyc")).unwrap(),
+            FileId(SmolStr::from("/tmp/a.awlyc"))
+        );
+    }
+}
@@ -0,0 +1,1222 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use awlyc_error::{Diagnostic, DiagnosticKind, FileId, Span};
+use awlyc_parser::{
+    ast::{Binop, BinopKind, Call, Expr, ExprIdx, RecordEntry, Spanned, TypeExpr},
+    Module,
+};
+use itertools::Itertools;
+use la_arena::Arena;
+use smol_str::SmolStr;
+
+use crate::{
+    bind_args, find_schema, similar_name, Argument, FnId, Resolution, Resolutions, SchemaId,
+};
+
+/// Static type of an expression
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Ty {
+    /// The type can't be known before evaluation, e.g. the type of a function parameter
+    Unknown,
+    Null,
+    Bool,
+    Int,
+    Float,
+    String,
+    Array(Box<Ty>),
+    Record(BTreeMap<SmolStr, Ty>),
+    /// Record type declared with `schema`, displayed by name
+    Schema {
+        name: SmolStr,
+        /// Tells schemas with the same name apart, the module and index of a schema declared in
+        /// awlyc or the Rust type a schema is derived for
+        id: SmolStr,
+        fields: BTreeMap<SmolStr, Ty>,
+        /// Whether fields the schema doesn't list are accepted, schemas declared in awlyc never
+        /// accept them but the schema of a Rust type does unless it denies unknown fields
+        open: bool,
+    },
+    /// Schema used inside its own fields, e.g. the elements of `children` in
+    /// `schema Tree { children: [Tree] }`
+    /// It's replaced by the schema each time the fields of the schema are taken, so a value is
+    /// checked against it as deeply as the value goes
+    Recursive {
+        name: SmolStr,
+        id: SmolStr,
+    },
+    /// `null` or a value of the inner type, record fields of this type can be left out
+    Optional(Box<Ty>),
+    Fn(Vec<Ty>, Box<Ty>),
+}
+
+impl Ty {
+    /// Builtin type with the given name, as written in annotations
+    pub fn builtin(name: &str) -> Option<Ty> {
+        let ty = match name {
+            "any" => Ty::Unknown,
+            "null" => Ty::Null,
+            "bool" => Ty::Bool,
+            "int" => Ty::Int,
+            "float" => Ty::Float,
+            "string" => Ty::String,
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    /// Type written in an annotation in `module_id`, schemas are expanded to their fields
+    /// Unknown names have been reported by the resolver and are treated as `any`
+    pub fn from_annotation(
+        ty: &TypeExpr,
+        module_id: &FileId,
+        modules: &HashMap<FileId, Module>,
+    ) -> Ty {
+        Ty::from_annotation_in(ty, module_id, modules, &mut vec![])
+    }
+
+    fn from_annotation_in(
+        ty: &TypeExpr,
+        module_id: &FileId,
+        modules: &HashMap<FileId, Module>,
+        expanding: &mut Vec<SchemaId>,
+    ) -> Ty {
+        let fields = |fields: &[(Spanned<SmolStr>, Spanned<TypeExpr>)],
+                      module_id: &FileId,
+                      expanding: &mut Vec<SchemaId>| {
+            fields
+                .iter()
+                .map(|(k, v)| {
+                    let ty = Ty::from_annotation_in(&v.inner, module_id, modules, expanding);
+                    (k.inner.clone(), ty)
+                })
+                .collect()
+        };
+        match ty {
+            TypeExpr::Named(path) => {
+                if let [name] = path.as_slice() {
+                    if let Some(ty) = Ty::builtin(name) {
+                        return ty;
+                    }
+                }
+                let id = match find_schema(modules, module_id, path) {
+                    Some(id) => id,
+                    None => return Ty::Unknown,
+                };
+                let schema = &modules[&id.module].schemas[id.idx];
+                let name = schema.name.inner.clone();
+                let schema_id = SmolStr::from(format!("{}#{}", id.module, id.idx));
+                if expanding.contains(&id) {
+                    return Ty::Recursive {
+                        name,
+                        id: schema_id,
+                    };
+                }
+                expanding.push(id.clone());
+                let fields = fields(&schema.fields, &id.module, expanding);
+                expanding.pop();
+                Ty::Schema {
+                    name,
+                    id: schema_id,
+                    fields,
+                    open: false,
+                }
+            }
+            TypeExpr::Array(el) => Ty::Array(Box::new(Ty::from_annotation_in(
+                &el.inner, module_id, modules, expanding,
+            ))),
+            TypeExpr::Record(record) => Ty::Record(fields(record, module_id, expanding)),
+            TypeExpr::Optional(ty) => Ty::Optional(Box::new(Ty::from_annotation_in(
+                &ty.inner, module_id, modules, expanding,
+            ))),
+            TypeExpr::Error => Ty::Unknown,
+        }
+    }
+
+    /// Whether the type is a record or schema type
+    pub fn is_record(&self) -> bool {
+        matches!(self, Ty::Record(_) | Ty::Schema { .. })
+    }
+
+    /// Fields of a record or schema type
+    /// Uses of a schema inside its own fields are replaced by the schema
+    pub fn fields(&self) -> Option<BTreeMap<SmolStr, Ty>> {
+        match self {
+            Ty::Record(fields) => Some(fields.clone()),
+            Ty::Schema { id, fields, .. } => Some(
+                fields
+                    .iter()
+                    .map(|(k, ty)| (k.clone(), ty.unfold(id, self)))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Replace uses of the schema `id` inside its own fields with `schema`
+    fn unfold(&self, id: &SmolStr, schema: &Ty) -> Ty {
+        let unfold = |ty: &Ty| ty.unfold(id, schema);
+        match self {
+            Ty::Recursive { id: this, .. } if this == id => schema.clone(),
+            Ty::Array(el) => Ty::Array(Box::new(unfold(el))),
+            Ty::Optional(ty) => Ty::Optional(Box::new(unfold(ty))),
+            Ty::Record(fields) => Ty::Record(
+                fields
+                    .iter()
+                    .map(|(k, ty)| (k.clone(), unfold(ty)))
+                    .collect(),
+            ),
+            // a schema inside another one can refer to the outer one, e.g. in mutually
+            // recursive schemas
+            Ty::Schema {
+                name,
+                id: this,
+                fields,
+                open,
+            } => Ty::Schema {
+                name: name.clone(),
+                id: this.clone(),
+                fields: fields
+                    .iter()
+                    .map(|(k, ty)| (k.clone(), unfold(ty)))
+                    .collect(),
+                open: *open,
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    /// Whether a value of type `found` can be used where `self` is expected
+    /// Ints are accepted as floats, and `any` on either side is accepted
+    pub fn accepts(&self, found: &Ty) -> bool {
+        self.mismatches(found).is_empty()
+    }
+
+    /// Every part of a value of type `found` that doesn't match `self`
+    pub fn mismatches(&self, found: &Ty) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+        self.collect_mismatches(found, &mut vec![], &mut mismatches);
+        mismatches
+    }
+
+    fn collect_mismatches(&self, found: &Ty, path: &mut Vec<Segment>, out: &mut Vec<Mismatch>) {
+        match (self, found) {
+            (Ty::Unknown, _) | (_, Ty::Unknown) | (Ty::Float, Ty::Int) => {}
+            (Ty::Optional(_), Ty::Null) => {}
+            (Ty::Optional(expected), Ty::Optional(found)) => {
+                expected.collect_mismatches(found, path, out)
+            }
+            // arrays and records are checked part by part, other values as a whole so the
+            // mismatch mentions the optional type
+            (
+                Ty::Optional(expected),
+                found @ (Ty::Array(_) | Ty::Record(_) | Ty::Schema { .. }),
+            ) => expected.collect_mismatches(found, path, out),
+            (Ty::Optional(expected), found) if expected.accepts(found) => {}
+            // which element has the wrong type isn't known statically
+            (Ty::Array(expected), Ty::Array(found)) if expected.accepts(found) => {}
+            (expected, found) if expected.is_record() && found.is_record() => {
+                let found = found.fields().unwrap();
+                out.extend(expected.field_mismatches(path, found.keys()));
+                for (k, ty) in expected.fields().unwrap() {
+                    if let Some(found) = found.get(&k) {
+                        path.push(Segment::Field(k));
+                        ty.collect_mismatches(found, path, out);
+                        path.pop();
+                    }
+                }
+            }
+            (expected, found) if expected == found => {}
+            (expected, found) => out.push(Mismatch {
+                path: path.clone(),
+                kind: MismatchKind::Type {
+                    expected: expected.clone(),
+                    found: found.clone(),
+                },
+            }),
+        }
+    }
+
+    /// Missing and unknown fields of a record with the keys `found`, checked against the record
+    /// or schema type `self`, an open schema has no unknown fields
+    pub fn field_mismatches<'k>(
+        &self,
+        path: &[Segment],
+        found: impl Iterator<Item = &'k SmolStr> + Clone,
+    ) -> Vec<Mismatch> {
+        let expected = self.fields().unwrap();
+        let missing = expected
+            .iter()
+            .filter(|(k, ty)| {
+                !matches!(ty, Ty::Optional(_)) && !found.clone().any(|found| found == *k)
+            })
+            .map(|(k, _)| k)
+            .map(|k| MismatchKind::MissingField {
+                field: k.clone(),
+                expected: self.clone(),
+            });
+        let open = matches!(self, Ty::Schema { open: true, .. });
+        let unknown = found
+            .clone()
+            .filter(|k| !open && !expected.contains_key(*k))
+            .sorted()
+            .map(|k| MismatchKind::UnknownField {
+                field: k.clone(),
+                expected: self.clone(),
+            });
+        missing
+            .chain(unknown)
+            .map(|kind| Mismatch {
+                path: path.to_vec(),
+                kind,
+            })
+            .collect()
+    }
+
+    /// Type of a value that is either of `self` or `other`, used for array elements
+    fn join(self, other: Ty) -> Ty {
+        if self == other {
+            self
+        } else {
+            Ty::Unknown
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Unknown => write!(f, "any"),
+            Ty::Null => write!(f, "null"),
+            Ty::Bool => write!(f, "bool"),
+            Ty::Int => write!(f, "int"),
+            Ty::Float => write!(f, "float"),
+            Ty::String => write!(f, "string"),
+            Ty::Array(el) => write!(f, "[{}]", el),
+            Ty::Record(fields) if fields.is_empty() => write!(f, "{{}}"),
+            Ty::Record(fields) => write!(
+                f,
+                "{{ {} }}",
+                fields
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, v))
+                    .join(", ")
+            ),
+            Ty::Schema { name, .. } | Ty::Recursive { name, .. } => write!(f, "{}", name),
+            Ty::Optional(ty) => write!(f, "{}?", ty),
+            Ty::Fn(params, ret) => write!(f, "fn({}) -> {}", params.iter().join(", "), ret),
+        }
+    }
+}
+
+/// Step from a value into one of its parts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Field(SmolStr),
+    Index(usize),
+}
+
+/// Part of a value that doesn't have the type it's expected to have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Path from the checked value to the mismatched part, for missing and unknown fields this is
+    /// the record the field belongs to
+    pub path: Vec<Segment>,
+    pub kind: MismatchKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MismatchKind {
+    /// A record lacks a field of the record or schema type `expected`
+    MissingField {
+        field: SmolStr,
+        expected: Ty,
+    },
+    /// A record has a field the record or schema type `expected` doesn't have
+    UnknownField {
+        field: SmolStr,
+        expected: Ty,
+    },
+    Type {
+        expected: Ty,
+        found: Ty,
+    },
+}
+
+/// `topics[0].name`
+pub fn display_path(path: &[Segment]) -> String {
+    let mut s = String::new();
+    for segment in path {
+        match segment {
+            Segment::Field(name) if s.is_empty() => s.push_str(name),
+            Segment::Field(name) => {
+                s.push('.');
+                s.push_str(name);
+            }
+            Segment::Index(idx) => s.push_str(&format!("[{}]", idx)),
+        }
+    }
+    s
+}
+
+impl Mismatch {
+    /// Path to the mismatched part, the field name is included for missing and unknown fields
+    fn path_to(&self, field: Option<&SmolStr>) -> String {
+        let mut path = self.path.clone();
+        path.extend(field.map(|f| Segment::Field(f.clone())));
+        display_path(&path)
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            MismatchKind::MissingField { field, expected } => write!(
+                f,
+                "missing field `{}` in `{}`",
+                self.path_to(Some(field)),
+                expected
+            ),
+            MismatchKind::UnknownField { field, expected } => write!(
+                f,
+                "unknown field `{}` in `{}`",
+                self.path_to(Some(field)),
+                expected
+            ),
+            MismatchKind::Type { expected, found } if self.path.is_empty() => write!(
+                f,
+                "mismatched types: expected `{}`, found `{}`",
+                expected, found
+            ),
+            MismatchKind::Type { expected, found } => write!(
+                f,
+                "mismatched types at `{}`: expected `{}`, found `{}`",
+                self.path_to(None),
+                expected,
+                found
+            ),
+        }
+    }
+}
+
+/// Expression that produced the part at `path` of the value of `expr`, following record and
+/// array literals and the bodies of called functions as far as they go
+/// Used to point diagnostics at the record literal a mismatched field comes from
+pub fn locate(
+    modules: &HashMap<FileId, Module>,
+    expr_arena: &Arena<Spanned<Expr>>,
+    resolutions: &Resolutions,
+    mut expr: ExprIdx,
+    mut path: &[Segment],
+) -> ExprIdx {
+    let mut called = vec![];
+    loop {
+        let next = match (&expr_arena[expr].inner, path) {
+            (Expr::Record(record), [Segment::Field(field), rest @ ..]) => {
+                record.field(field).map(|field| (field, rest))
+            }
+            (Expr::Array(els), [Segment::Index(idx), rest @ ..]) => {
+                els.get(*idx).map(|el| (*el, rest))
+            }
+            (Expr::Call(call), _) => match resolutions.get(call.callee) {
+                // recursive calls would be followed forever
+                Some(Resolution::Function(fn_id)) if !called.contains(fn_id) => {
+                    called.push(fn_id.clone());
+                    Some((modules[&fn_id.module].functions[fn_id.idx].body, path))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        match next {
+            Some((next, rest)) => {
+                expr = next;
+                path = rest;
+            }
+            None => return expr,
+        }
+    }
+}
+
+/// Error for spreading a value of type `ty` into the arguments of a call
+pub fn not_spreadable(ty: &Ty, span: Span) -> Diagnostic {
+    Diagnostic {
+        kind: DiagnosticKind::Error,
+        msg: format!("only arrays can be spread into arguments, found `{}`", ty),
+        span,
+        labels: vec![],
+    }
+}
+
+/// Error for accessing `field` of a value of type `ty` that doesn't have it
+/// A field of the record with a similar name is suggested
+pub fn no_field(field: &str, ty: &Ty, span: Span) -> Diagnostic {
+    let labels = ty
+        .fields()
+        .and_then(|fields| similar_name(field, fields.keys()).cloned())
+        .map(|similar| (span.clone(), format!("did you mean `{}`?", similar)));
+    Diagnostic {
+        kind: DiagnosticKind::Error,
+        msg: format!("no field `{}` on `{}`", field, ty),
+        span,
+        labels: labels.into_iter().collect(),
+    }
+}
+
+/// Error for indexing a value of type `ty` with an index of type `index`
+pub fn invalid_index(ty: &Ty, index: &Ty, span: Span) -> Diagnostic {
+    let msg = match ty {
+        Ty::Array(_) => format!("arrays are indexed with `int`, found `{}`", index),
+        ty if ty.is_record() => {
+            format!("records are indexed with `string`, found `{}`", index)
+        }
+        ty => format!("cannot index into a value of type `{}`", ty),
+    };
+    Diagnostic {
+        kind: DiagnosticKind::Error,
+        msg,
+        span,
+        labels: vec![],
+    }
+}
+
+/// Types an operand whose type is unknown might turn out to have
+const OPERAND_CANDIDATES: &[Ty] = &[Ty::Int, Ty::Float, Ty::String];
+
+/// Result type of a binary operation on operands of known types
+fn binop_ty(op: &BinopKind, lhs: &Ty, rhs: &Ty) -> Option<Ty> {
+    let ty = match (op, lhs, rhs) {
+        (_, Ty::Int, Ty::Int) => Ty::Int,
+        (_, Ty::Int | Ty::Float, Ty::Int | Ty::Float) => Ty::Float,
+        (BinopKind::Add, Ty::String, Ty::String) => Ty::String,
+        (BinopKind::Mul, Ty::String, Ty::Int) => Ty::String,
+        _ => return None,
+    };
+    Some(ty)
+}
+
+/// Result type of merging two records, `None` if either operand can't be a record
+fn merge_ty(lhs: &Ty, rhs: &Ty) -> Option<Ty> {
+    match (lhs.fields(), rhs.fields()) {
+        (Some(lhs), Some(rhs)) => {
+            let mut fields = lhs;
+            for (k, rhs) in rhs {
+                let ty = match fields.get(&k) {
+                    Some(lhs) if lhs.is_record() && rhs.is_record() => merge_ty(lhs, &rhs).unwrap(),
+                    // merging with a field of unknown type could produce anything
+                    Some(Ty::Unknown) if rhs.is_record() => Ty::Unknown,
+                    _ => rhs,
+                };
+                fields.insert(k, ty);
+            }
+            Some(Ty::Record(fields))
+        }
+        _ if [lhs, rhs]
+            .iter()
+            .all(|ty| ty.is_record() || **ty == Ty::Unknown) =>
+        {
+            Some(Ty::Unknown)
+        }
+        _ => None,
+    }
+}
+
+/// Infers the type of every expression and reports operations on values of the wrong type
+/// Every function is checked on its own, whether or not it ends up being called, with parameters
+/// of unknown type
+/// At call sites the callee's body is inferred again with the types of the arguments, this only
+/// refines the result type of the call and doesn't report anything
+pub struct TypeChecker<'a> {
+    modules: &'a HashMap<FileId, Module>,
+    expr_arena: &'a Arena<Spanned<Expr>>,
+    resolutions: &'a Resolutions,
+    /// Types of functions that have been checked
+    fn_types: HashMap<FnId, Ty>,
+    /// Result types of calls, by callee and argument types
+    instantiations: HashMap<(FnId, Vec<Ty>), Ty>,
+    /// Functions being inferred, calls to these are recursive and have an unknown result type
+    stack: Vec<FnId>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new(
+        modules: &'a HashMap<FileId, Module>,
+        expr_arena: &'a Arena<Spanned<Expr>>,
+        resolutions: &'a Resolutions,
+    ) -> Self {
+        Self {
+            modules,
+            expr_arena,
+            resolutions,
+            fn_types: HashMap::new(),
+            instantiations: HashMap::new(),
+            stack: vec![],
+            diagnostics: vec![],
+        }
+    }
+
+    pub fn finish(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+
+    /// Check every function and expression of every module
+    pub fn check_modules(&mut self) {
+        let modules = self.modules;
+        for (module_id, module) in modules.iter().sorted_by(|(a, _), (b, _)| a.0.cmp(&b.0)) {
+            for idx in 0..module.functions.len() {
+                self.fn_ty(&FnId {
+                    module: module_id.clone(),
+                    idx,
+                });
+            }
+            if let Some(expr) = module.expr {
+                self.infer_expr(expr);
+            }
+        }
+    }
+
+    /// Type of an expression outside of any function, mismatches are reported
+    pub fn infer_expr(&mut self, expr: ExprIdx) -> Ty {
+        self.infer(expr, &[], true)
+    }
+
+    /// Type of a function, mismatches in its body are reported the first time it's checked
+    pub fn fn_ty(&mut self, fn_id: &FnId) -> Ty {
+        if let Some(ty) = self.fn_types.get(fn_id) {
+            return ty.clone();
+        }
+        let f = &self.modules[&fn_id.module].functions[fn_id.idx];
+        let params = self.param_tys(fn_id);
+        for (param, expected) in f.params.0.iter().zip(&params) {
+            if param.variadic && !matches!(expected, Ty::Array(_) | Ty::Unknown) {
+                let span = param.ty.as_ref().map_or(&param.name.span, |ty| &ty.span);
+                self.mismatch(
+                    true,
+                    format!(
+                        "variadic parameters are annotated with an array type, found `{}`",
+                        expected
+                    ),
+                    span.clone(),
+                );
+            }
+            if let Some(default) = param.default {
+                let ty = self.infer(default, &[], true);
+                self.expect(expected, &ty, true, default);
+            }
+        }
+        self.stack.push(fn_id.clone());
+        let mut ret = self.infer(f.body, &params, true);
+        self.stack.pop();
+        if let Some(expected) = self.ret_ty(fn_id) {
+            self.expect(&expected, &ret, true, f.body);
+            ret = expected;
+        }
+        let ty = Ty::Fn(params, Box::new(ret));
+        self.fn_types.insert(fn_id.clone(), ty.clone());
+        ty
+    }
+
+    /// Signature of a function as it would be written with annotations, inferred types are shown
+    /// for parameters and return values without one
+    pub fn signature(&mut self, fn_id: &FnId) -> String {
+        let f = &self.modules[&fn_id.module].functions[fn_id.idx];
+        let (params, ret) = match self.fn_ty(fn_id) {
+            Ty::Fn(params, ret) => (params, ret),
+            _ => unreachable!(),
+        };
+        format!(
+            "fn {}({}) -> {}",
+            f.name.inner,
+            f.params
+                .0
+                .iter()
+                .zip(params)
+                .map(|(param, ty)| {
+                    let dots = if param.variadic { "..." } else { "" };
+                    format!("{}{}: {}", dots, param.name.inner, ty)
+                })
+                .join(", "),
+            ret
+        )
+    }
+
+    /// Report every mismatch of `found`, the type of `expr`, with its annotated type `expected`
+    fn expect(&mut self, expected: &Ty, found: &Ty, report: bool, expr: ExprIdx) {
+        for mismatch in expected.mismatches(found) {
+            let at = locate(
+                self.modules,
+                self.expr_arena,
+                self.resolutions,
+                expr,
+                &mismatch.path,
+            );
+            self.mismatch(
+                report,
+                mismatch.to_string(),
+                self.expr_arena[at].span.clone(),
+            );
+        }
+    }
+
+    /// Types of a function's parameters, `any` for those without an annotation and `[any]` for a
+    /// variadic one
+    fn param_tys(&self, fn_id: &FnId) -> Vec<Ty> {
+        let f = &self.modules[&fn_id.module].functions[fn_id.idx];
+        f.params
+            .0
+            .iter()
+            .map(|param| match &param.ty {
+                Some(ty) => Ty::from_annotation(&ty.inner, &fn_id.module, self.modules),
+                None if param.variadic => Ty::Array(Box::new(Ty::Unknown)),
+                None => Ty::Unknown,
+            })
+            .collect()
+    }
+
+    /// Type of the argument `arg` of a call to `fn_id`, `passed` are the types of the arguments in
+    /// the call and `expected` the annotated type of the parameter
+    fn arg_ty(
+        &mut self,
+        fn_id: &FnId,
+        call: &Call,
+        passed: &[Ty],
+        arg: &Argument,
+        expected: Ty,
+        report: bool,
+    ) -> Ty {
+        let ty = match arg {
+            Argument::Passed(idx) => {
+                let ty = passed[*idx].clone();
+                self.expect(&expected, &ty, report, call.args[*idx].value);
+                ty
+            }
+            Argument::Default(default) => self.default_ty(fn_id, *default),
+            Argument::Rest(args) => {
+                let el = match &expected {
+                    Ty::Array(el) => (**el).clone(),
+                    _ => Ty::Unknown,
+                };
+                let el = args
+                    .iter()
+                    .map(|arg| self.arg_ty(fn_id, call, passed, arg, el.clone(), report))
+                    .reduce(Ty::join)
+                    .unwrap_or(el);
+                Ty::Array(Box::new(el))
+            }
+            // the lengths of spread arrays aren't known yet
+            Argument::Element(..) | Argument::Unknown => Ty::Unknown,
+        };
+        // the annotation says more about an argument than its inferred type
+        if ty == Ty::Unknown {
+            expected
+        } else {
+            ty
+        }
+    }
+
+    /// Type of the default value of a parameter of `fn_id`, its mismatches are reported once with
+    /// the function
+    fn default_ty(&mut self, fn_id: &FnId, default: ExprIdx) -> Ty {
+        // a default value can call the function it belongs to
+        if self.stack.contains(fn_id) {
+            return Ty::Unknown;
+        }
+        self.stack.push(fn_id.clone());
+        let ty = self.infer(default, &[], false);
+        self.stack.pop();
+        ty
+    }
+
+    fn ret_ty(&self, fn_id: &FnId) -> Option<Ty> {
+        let f = &self.modules[&fn_id.module].functions[fn_id.idx];
+        f.ret_ty
+            .as_ref()
+            .map(|ty| Ty::from_annotation(&ty.inner, &fn_id.module, self.modules))
+    }
+
+    fn mismatch(&mut self, report: bool, msg: String, span: Span) -> Ty {
+        self.report(
+            report,
+            Diagnostic {
+                kind: DiagnosticKind::Error,
+                msg,
+                span,
+                labels: vec![],
+            },
+        )
+    }
+
+    fn report(&mut self, report: bool, diagnostic: Diagnostic) -> Ty {
+        if report {
+            self.diagnostics.push(diagnostic);
+        }
+        Ty::Unknown
+    }
+
+    /// Type of the field `field` of a value of type `ty`
+    /// Fields of optional values are only checked during evaluation
+    fn field_ty(&mut self, ty: &Ty, field: &str, span: Span, report: bool) -> Ty {
+        match ty {
+            Ty::Unknown | Ty::Optional(_) => Ty::Unknown,
+            ty => match ty.fields().and_then(|mut fields| fields.remove(field)) {
+                Some(field) => field,
+                None => self.report(report, no_field(field, ty, span)),
+            },
+        }
+    }
+
+    /// `params` are the types of the parameters of the enclosing function
+    /// Mismatches are only reported if `report` is set
+    fn infer(&mut self, idx: ExprIdx, params: &[Ty], report: bool) -> Ty {
+        let expr_arena = self.expr_arena;
+        match &expr_arena[idx].inner {
+            Expr::Path(_) => match self.resolutions.get(idx) {
+                Some(Resolution::Param(param)) => params[*param].clone(),
+                _ => Ty::Unknown,
+            },
+            Expr::Int(_) => Ty::Int,
+            Expr::Float(_) => Ty::Float,
+            Expr::String(_) => Ty::String,
+            Expr::Bool(_) => Ty::Bool,
+            Expr::Null => Ty::Null,
+            Expr::Error => Ty::Unknown,
+            Expr::Array(els) => {
+                let el = els
+                    .iter()
+                    .map(|el| self.infer(*el, params, report))
+                    .reduce(Ty::join)
+                    .unwrap_or(Ty::Unknown);
+                Ty::Array(Box::new(el))
+            }
+            Expr::Record(record) => {
+                // the fields of a record of unknown type could be spread into this one
+                let mut fields = Some(BTreeMap::new());
+                for entry in &record.entries {
+                    match entry {
+                        RecordEntry::Field(key, value) => {
+                            let ty = self.infer(*value, params, report);
+                            if let Some(fields) = &mut fields {
+                                fields.insert(key.clone(), ty);
+                            }
+                        }
+                        RecordEntry::Spread(spread) => match self.infer(*spread, params, report) {
+                            Ty::Unknown => fields = None,
+                            ty if ty.is_record() => {
+                                if let Some(fields) = &mut fields {
+                                    fields.extend(ty.fields().unwrap());
+                                }
+                            }
+                            ty => {
+                                self.mismatch(
+                                    report,
+                                    format!("only records can be spread, found `{}`", ty),
+                                    expr_arena[*spread].span.clone(),
+                                );
+                            }
+                        },
+                    }
+                }
+                fields.map_or(Ty::Unknown, Ty::Record)
+            }
+            Expr::Negate(negate) => match self.infer(negate.expr, params, report) {
+                ty @ (Ty::Int | Ty::Float | Ty::Unknown) => ty,
+                ty => self.mismatch(
+                    report,
+                    format!("invalid negation operation: cannot negate `{}`", ty),
+                    expr_arena[idx].span.clone(),
+                ),
+            },
+            Expr::Binop(binop) => self.infer_binop(binop, params, report),
+            Expr::Field(field) => {
+                let ty = self.infer(field.expr, params, report);
+                self.field_ty(&ty, &field.field, field.field.span.clone(), report)
+            }
+            Expr::Index(index) => {
+                let ty = self.infer(index.expr, params, report);
+                let index_ty = self.infer(index.index, params, report);
+                let span = expr_arena[index.index].span.clone();
+                match (&ty, &index_ty) {
+                    (Ty::Unknown | Ty::Optional(_), _) => Ty::Unknown,
+                    (Ty::Array(el), Ty::Int | Ty::Unknown) => (**el).clone(),
+                    // only keys written as literals are known before evaluation
+                    (ty, Ty::String) if ty.is_record() => match &expr_arena[index.index].inner {
+                        Expr::String(key) => self.field_ty(ty, key, span, report),
+                        _ => Ty::Unknown,
+                    },
+                    (ty, Ty::Unknown) if ty.is_record() => Ty::Unknown,
+                    (ty, index_ty) => {
+                        let span = expr_arena[idx].span.clone();
+                        self.report(report, invalid_index(ty, index_ty, span))
+                    }
+                }
+            }
+            Expr::Call(call) => {
+                let passed: Vec<_> = call
+                    .args
+                    .iter()
+                    .map(|arg| {
+                        let ty = self.infer(arg.value, params, report);
+                        // optional values are only checked during evaluation
+                        let spreadable = matches!(ty, Ty::Array(_) | Ty::Optional(_) | Ty::Unknown);
+                        if arg.spread && !spreadable {
+                            let span = expr_arena[arg.value].span.clone();
+                            self.report(report, not_spreadable(&ty, span));
+                        }
+                        ty
+                    })
+                    .collect();
+                let fn_id = match self.resolutions.get(call.callee) {
+                    Some(Resolution::Function(fn_id)) => fn_id,
+                    _ => return Ty::Unknown,
+                };
+                let f = &self.modules[&fn_id.module].functions[fn_id.idx];
+                // binding errors have been reported by the resolver
+                let bound = match bind_args(f, call, expr_arena, None) {
+                    Ok(bound) => bound,
+                    Err(_) => return Ty::Unknown,
+                };
+                let args = bound
+                    .iter()
+                    .zip(self.param_tys(fn_id))
+                    .map(|(arg, expected)| self.arg_ty(fn_id, call, &passed, arg, expected, report))
+                    .collect();
+                match self.ret_ty(fn_id) {
+                    Some(ret) => ret,
+                    None => self.instantiate(fn_id, args),
+                }
+            }
+        }
+    }
+
+    fn infer_binop(&mut self, binop: &Binop, params: &[Ty], report: bool) -> Ty {
+        let lhs = self.infer(binop.lhs, params, report);
+        let rhs = self.infer(binop.rhs, params, report);
+
+        if let BinopKind::Merge = binop.op {
+            return match merge_ty(&lhs, &rhs) {
+                Some(ty) => ty,
+                None => self.mismatch(
+                    report,
+                    format!("invalid merge operands: `{}` & `{}`", lhs, rhs),
+                    Span::combine(
+                        &self.expr_arena[binop.lhs].span,
+                        &self.expr_arena[binop.rhs].span,
+                    ),
+                ),
+            };
+        }
+
+        // an unknown operand could be of any type that makes the operation valid
+        let candidates = |ty: &Ty| match ty {
+            Ty::Unknown => OPERAND_CANDIDATES.to_vec(),
+            ty => vec![ty.clone()],
+        };
+        let results: Vec<_> = candidates(&lhs)
+            .iter()
+            .cartesian_product(candidates(&rhs).iter())
+            .filter_map(|(lhs, rhs)| binop_ty(&binop.op, lhs, rhs))
+            .unique()
+            .collect();
+
+        match results.as_slice() {
+            [] => {
+                let name = match binop.op {
+                    BinopKind::Add => "addition",
+                    BinopKind::Sub => "subtraction",
+                    BinopKind::Mul => "multiplication",
+                    BinopKind::Div => "division",
+                    BinopKind::Rem => "remainder",
+                    BinopKind::Pow => "exponentiation",
+                    BinopKind::Merge => unreachable!(),
+                };
+                self.mismatch(
+                    report,
+                    format!(
+                        "invalid {} operands: `{}` {} `{}`",
+                        name,
+                        lhs,
+                        binop.op.symbol(),
+                        rhs
+                    ),
+                    Span::combine(
+                        &self.expr_arena[binop.lhs].span,
+                        &self.expr_arena[binop.rhs].span,
+                    ),
+                )
+            }
+            [ty] if lhs != Ty::Unknown || rhs != Ty::Unknown => ty.clone(),
+            _ => Ty::Unknown,
+        }
+    }
+
+    /// Result type of calling a function with arguments of the given types
+    fn instantiate(&mut self, fn_id: &FnId, args: Vec<Ty>) -> Ty {
+        if self.stack.contains(fn_id) {
+            return Ty::Unknown;
+        }
+        let key = (fn_id.clone(), args);
+        if let Some(ty) = self.instantiations.get(&key) {
+            return ty.clone();
+        }
+
+        let f = &self.modules[&fn_id.module].functions[fn_id.idx];
+        self.stack.push(fn_id.clone());
+        let ty = self.infer(f.body, &key.1, false);
+        self.stack.pop();
+        self.instantiations.insert(key, ty.clone());
+        ty
+    }
+}
+
+/// Type check all modules
+pub fn check(
+    modules: &HashMap<FileId, Module>,
+    expr_arena: &Arena<Spanned<Expr>>,
+    resolutions: &Resolutions,
+) -> Vec<Diagnostic> {
+    let mut checker = TypeChecker::new(modules, expr_arena, resolutions);
+    checker.check_modules();
+    checker.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use awlyc_error::FileId;
+    use awlyc_parser::parse;
+    use la_arena::Arena;
+    use smol_str::SmolStr;
+
+    use super::TypeChecker;
+    use crate::{resolve, FnId};
+
+    /// Messages of all type errors, and the type of the module's expression
+    /// If there is no expression the type of the first function is returned instead
+    fn check_src(src: &str) -> (Vec<String>, String) {
+        let file_id = FileId(SmolStr::from("main"));
+        let mut expr_arena = Arena::default();
+        let (module, errors) = parse(src, &mut expr_arena, file_id.clone());
+        assert!(errors.is_empty(), "{:#?}", errors);
+        let expr = module.expr;
+        let mut modules = HashMap::from([(file_id.clone(), module)]);
+        let (resolutions, errors) = resolve(&modules, &expr_arena);
+        assert!(errors.is_empty(), "{:#?}", errors);
+        // the expression is inferred below, it shouldn't be checked a second time with the module
+        modules.get_mut(&file_id).unwrap().expr = None;
+
+        let mut checker = TypeChecker::new(&modules, &expr_arena, &resolutions);
+        let ty = match expr {
+            Some(expr) => checker.infer_expr(expr).to_string(),
+            None => checker
+                .fn_ty(&FnId {
+                    module: FileId(SmolStr::from("main")),
+                    idx: 0,
+                })
+                .to_string(),
+        };
+        checker.check_modules();
+        let msgs = checker.finish().into_iter().map(|d| d.msg).collect();
+        (msgs, ty)
+    }
+
+    #[test]
+    fn mismatches_in_uncalled_functions() {
+        let (msgs, _) =
+            check_src(r#"fn a(x) { "a" - 1 } fn b(x) { -{ x: x } } fn c(x) { x / "2" }"#);
+        assert_eq!(
+            msgs,
+            [
+                "invalid subtraction operands: `string` - `int`",
+                "invalid negation operation: cannot negate `{ x: any }`",
+                "invalid division operands: `any` / `string`",
+            ]
+        );
+    }
+
+    #[test]
+    fn call_result_types() {
+        let (msgs, ty) = check_src(
+            r#"fn project(name, stars) { { name, stars: stars * 2, tags: [name, "x"] } } project("a", 1.5)"#,
+        );
+        assert!(msgs.is_empty(), "{:#?}", msgs);
+        assert_eq!(ty, "{ name: string, stars: float, tags: [string] }");
+    }
+
+    #[test]
+    fn partially_known_operands() {
+        let (msgs, ty) = check_src(r#"fn greet(x) { x + "!" } [greet(1), "a" * 3, [1, 2.0]]"#);
+        assert!(msgs.is_empty(), "{:#?}", msgs);
+        assert_eq!(ty, "[any]");
+
+        // `x` has to be a string for `+ "!"` to be valid
+        let (_, ty) = check_src(r#"fn greet(x) { x + "!" }"#);
+        assert_eq!(ty, "fn(any) -> string");
+    }
+
+    #[test]
+    fn annotations() {
+        let (msgs, ty) = check_src(
+            r#"fn project(title: string, stars: float) -> { title: string } { { title: title, stars: stars } } fn id(x) { x } [project(id(1), 2), project("a", 3)]"#,
+        );
+        assert_eq!(
+            msgs,
+            [
+                "mismatched types: expected `string`, found `int`",
+                "unknown field `stars` in `{ title: string }`",
+            ]
+        );
+        assert_eq!(ty, "[{ title: string }]");
+
+        // unannotated parameters take the type of their annotation at call sites
+        let (msgs, ty) = check_src(r#"fn g(x) { f(x) } fn f(x: int) { x * 2 }"#);
+        assert!(msgs.is_empty(), "{:#?}", msgs);
+        assert_eq!(ty, "fn(any) -> int");
+    }
+
+    #[test]
+    fn schemas() {
+        let (msgs, ty) = check_src(
+            r#"schema Topic { name: string }
+            schema Project { name: string, repo: string, topics: [Topic] }
+            fn topic(name: string) -> Topic { { name: name } }
+            fn project(name: string) -> Project { { name: name, topics: [topic("a")], stars: 1 } }
+            project(1)"#,
+        );
+        assert_eq!(
+            msgs,
+            [
+                "mismatched types: expected `string`, found `int`",
+                "missing field `repo` in `Project`",
+                "unknown field `stars` in `Project`",
+            ]
+        );
+        assert_eq!(ty, "Project");
+    }
+
+    #[test]
+    fn recursive_schemas() {
+        let (msgs, ty) = check_src(
+            r#"schema Tree { name: string, children: [Tree] }
+            schema Page { title: string, parent: Page? }
+            fn leaf(name: string) -> Tree { { name: name, children: [] } }
+            fn page() -> Page { { title: "b", parent: { title: "a", parent: null } } }
+            fn tree() -> Tree { { name: "b", children: [leaf("c"), { name: "d", children: [] }] } }
+            tree()"#,
+        );
+        assert!(msgs.is_empty(), "{:#?}", msgs);
+        assert_eq!(ty, "Tree");
+
+        let (msgs, _) = check_src(
+            r#"schema Tree { name: string, children: [Tree] }
+            fn t() -> Tree: { name: "root", children: [{ name: "a", children: [{ name: 1, children: [] }] }] }"#,
+        );
+        assert_eq!(
+            msgs,
+            ["mismatched types at `children`: expected `[Tree]`, found `[{ children: [{ children: [any], name: int }], name: string }]`"]
+        );
+    }
+
+    #[test]
+    fn optional_fields() {
+        let (msgs, _) = check_src(
+            r#"schema Page { title: string, draft: int? }
+            fn a() -> Page { { title: "a" } }
+            fn b() -> Page { { title: "b", draft: null } }
+            fn c() -> Page { { title: "c", draft: "yes" } }"#,
+        );
+        assert_eq!(
+            msgs,
+            ["mismatched types at `draft`: expected `int?`, found `string`"]
+        );
+    }
+
+    #[test]
+    fn spread_and_merge_types() {
+        let (msgs, ty) =
+            check_src(r#"{ ...{ a: 1, b: { c: "c" } }, a: "a" } & { b: { d: null }, e: [1.5] }"#);
+        assert!(msgs.is_empty(), "{:#?}", msgs);
+        assert_eq!(ty, "{ a: string, b: { c: string, d: null }, e: [float] }");
+
+        let (msgs, ty) = check_src(r#"{ a: "a", ...{ a: 1, b: true } }"#);
+        assert!(msgs.is_empty(), "{:#?}", msgs);
+        assert_eq!(ty, "{ a: int, b: bool }");
+
+        let (msgs, _) = check_src(r#"[{ ...[1] }, { a: 1 } & 2]"#);
+        assert_eq!(
+            msgs,
+            [
+                "only records can be spread, found `[int]`",
+                "invalid merge operands: `{ a: int }` & `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn field_access_and_indexing() {
+        let (msgs, ty) = check_src(
+            r#"fn page() { { title: "a", tags: ["b"], meta: { stars: 1 } } }
+            { a: page().meta.stars, b: page().tags[0], c: page()["meta"].stars }"#,
+        );
+        assert!(msgs.is_empty(), "{:#?}", msgs);
+        assert_eq!(ty, "{ a: int, b: string, c: int }");
+
+        // parameters are only checked during evaluation
+        let (msgs, _) = check_src(
+            r#"fn page() { { title: "a", tags: ["b"] } } fn f(x) { [x.y, x[0], page()[x]] }
+            [page().titel, page().tags["a"], page().title[0]]"#,
+        );
+        assert_eq!(
+            msgs,
+            [
+                "no field `titel` on `{ tags: [string], title: string }`",
+                "arrays are indexed with `int`, found `string`",
+                "cannot index into a value of type `string`",
+            ]
+        );
+    }
+
+    #[test]
+    fn default_values() {
+        let (msgs, ty) = check_src(
+            r#"fn project(name, stars = 0, tags: [string] = []) { { name: name, stars: stars, tags: tags } }
+            fn loop(x = loop()) { x }
+            [project("a"), project(stars: 1.5, name: "b")]"#,
+        );
+        assert!(msgs.is_empty(), "{:#?}", msgs);
+        assert_eq!(ty, "[any]");
+
+        let (msgs, _) = check_src(r#"fn f(x: int = "a", y: string = "b") { x } f(y: 1)"#);
+        assert_eq!(
+            msgs,
+            [
+                "mismatched types: expected `string`, found `int`",
+                "mismatched types: expected `int`, found `string`",
+            ]
+        );
+    }
+
+    #[test]
+    fn variadics() {
+        let (msgs, ty) = check_src(
+            r#"fn tags(...items: [string]) { items } fn first(...xs) { xs[0] }
+            [tags(), tags("a", ...["b"]), first(1, 2)]"#,
+        );
+        assert!(msgs.is_empty(), "{:#?}", msgs);
+        assert_eq!(ty, "[any]");
+
+        let (msgs, _) =
+            check_src(r#"fn f(...xs: int) { xs } fn g(...xs: [int]) { xs } [g(1, "a"), g(...1)]"#);
+        assert_eq!(
+            msgs,
+            [
+                "mismatched types: expected `int`, found `string`",
+                "only arrays can be spread into arguments, found `int`",
+                "variadic parameters are annotated with an array type, found `int`",
+            ]
+        );
+    }
+}
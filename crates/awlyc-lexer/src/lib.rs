@@ -1,3 +1,5 @@
+use std::fmt;
+
 use logos::Logos;
 use smol_str::SmolStr;
 use text_size::{TextRange, TextSize};
@@ -50,8 +52,14 @@ pub enum TokenKind {
     Fn,
     #[token("import")]
     Import,
+    #[token("schema")]
+    Schema,
     #[token("null")]
     Null,
+    #[token("true")]
+    True,
+    #[token("false")]
+    False,
     #[token("(")]
     LParen,
     #[token(")")]
@@ -68,6 +76,8 @@ pub enum TokenKind {
     Comma,
     #[token(":")]
     Colon,
+    #[token("=")]
+    Eq,
     #[token("\"")]
     DoubleQuote,
     #[token("+")]
@@ -78,13 +88,25 @@ pub enum TokenKind {
     Star,
     #[token("/")]
     FSlah,
+    #[token("%")]
+    Percent,
+    #[token("**")]
+    StarStar,
     #[token(".")]
     Period,
+    #[token("...")]
+    Ellipsis,
+    #[token("&")]
+    Amp,
+    #[token("->")]
+    Arrow,
+    #[token("?")]
+    Question,
 
     #[regex("[A-Za-z_][A-Za-z0-9_]*")]
     Ident,
 
-    #[regex(r#""(?:[^"]|\\")*""#)]
+    #[regex(r#""(?:[^"\\]|\\.)*""#)]
     StringLit,
 
     #[regex("0x[0-9a-fA-F]+(_[0-9a-fA-F]+)*")]
@@ -104,6 +126,48 @@ pub enum TokenKind {
     Error,
 }
 
+impl fmt::Display for TokenKind {
+    /// How the token is referred to in diagnostics, punctuation and keywords are quoted
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TokenKind::Fn => "'fn'",
+            TokenKind::Import => "'import'",
+            TokenKind::Schema => "'schema'",
+            TokenKind::Null => "'null'",
+            TokenKind::True => "'true'",
+            TokenKind::False => "'false'",
+            TokenKind::LParen => "'('",
+            TokenKind::RParen => "')'",
+            TokenKind::LCurly => "'{'",
+            TokenKind::RCurly => "'}'",
+            TokenKind::LSquare => "'['",
+            TokenKind::RSquare => "']'",
+            TokenKind::Comma => "','",
+            TokenKind::Colon => "':'",
+            TokenKind::Eq => "'='",
+            TokenKind::DoubleQuote => "'\"'",
+            TokenKind::Plus => "'+'",
+            TokenKind::Minus => "'-'",
+            TokenKind::Star => "'*'",
+            TokenKind::FSlah => "'/'",
+            TokenKind::Percent => "'%'",
+            TokenKind::StarStar => "'**'",
+            TokenKind::Period => "'.'",
+            TokenKind::Ellipsis => "'...'",
+            TokenKind::Amp => "'&'",
+            TokenKind::Arrow => "'->'",
+            TokenKind::Question => "'?'",
+            TokenKind::Ident => "identifier",
+            TokenKind::StringLit => "string",
+            TokenKind::IntLit => "integer",
+            TokenKind::FloatLit => "float",
+            TokenKind::Comment => "comment",
+            TokenKind::Error => "invalid token",
+        };
+        f.write_str(s)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::lex;
@@ -126,4 +190,7 @@ mod tests {
     lex_str!(basic_input, "testing 1.30 249 _hi02");
     lex_str!(keywords, "fn");
     lex_str!(separators, "(){}[],");
+    lex_str!(operators, "+ - * ** / % & . ...");
+    lex_str!(optional_types, "int? [string]?");
+    lex_str!(strings, r#""a \"b\" \\" "\\""#);
 }
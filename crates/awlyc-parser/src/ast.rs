@@ -1,4 +1,4 @@
-use std::{collections::HashMap, ops::Deref};
+use std::{fmt, ops::Deref};
 
 use awlyc_error::Span;
 use la_arena::Idx;
@@ -7,7 +7,7 @@ use smol_str::SmolStr;
 
 pub type ExprIdx = Idx<Spanned<Expr>>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Spanned<T> {
     pub inner: T,
     pub span: Span,
@@ -27,22 +27,79 @@ pub enum Expr {
     Int(u64),
     Float(f64),
     String(SmolStr),
+    Bool(bool),
     Array(SmallVec<[ExprIdx; 2]>),
     Record(Record),
     Binop(Binop),
     Negate(Negate),
     Call(Call),
+    Field(Field),
+    Index(Index),
     Null,
     Error,
 }
 
 #[derive(Debug)]
-pub struct Record(pub HashMap<SmolStr, ExprIdx>);
+pub struct Record {
+    /// Fields and spreads in source order, a later entry overrides the fields of earlier ones
+    pub entries: Vec<RecordEntry>,
+}
+
+#[derive(Debug)]
+pub enum RecordEntry {
+    Field(SmolStr, ExprIdx),
+    /// `...base`, a record whose fields are copied into this one
+    Spread(ExprIdx),
+}
+
+impl Record {
+    /// Fields written in the literal, in source order
+    pub fn fields(&self) -> impl Iterator<Item = (&SmolStr, ExprIdx)> {
+        self.entries.iter().filter_map(|entry| match entry {
+            RecordEntry::Field(key, value) => Some((key, *value)),
+            RecordEntry::Spread(_) => None,
+        })
+    }
+
+    /// Expression of the field `key`, `None` if it isn't written in the literal or a later spread
+    /// could override it
+    pub fn field(&self, key: &str) -> Option<ExprIdx> {
+        self.entries.iter().rev().find_map(|entry| match entry {
+            RecordEntry::Field(k, value) if k == key => Some(Some(*value)),
+            RecordEntry::Field(..) => None,
+            RecordEntry::Spread(_) => Some(None),
+        })?
+    }
+}
 
 #[derive(Debug)]
 pub struct Call {
     pub callee: ExprIdx,
-    pub args: Spanned<Vec<ExprIdx>>,
+    /// Positional arguments come before named ones
+    pub args: Spanned<Vec<Arg>>,
+}
+
+/// Argument of a call, `x`, `name: x` or `...xs`
+#[derive(Debug)]
+pub struct Arg {
+    pub name: Option<Spanned<SmolStr>>,
+    pub value: ExprIdx,
+    /// The elements of the array `value` are passed as positional arguments, `f(...list)`
+    pub spread: bool,
+}
+
+/// `expr.field`
+#[derive(Debug)]
+pub struct Field {
+    pub expr: ExprIdx,
+    pub field: Spanned<SmolStr>,
+}
+
+/// `expr[0]` or `expr["key"]`
+#[derive(Debug)]
+pub struct Index {
+    pub expr: ExprIdx,
+    pub index: ExprIdx,
 }
 
 #[derive(Debug)]
@@ -56,6 +113,27 @@ pub enum BinopKind {
     Sub,
     Mul,
     Div,
+    Rem,
+    /// `base ** exponent`
+    Pow,
+    /// `base & override`, records are merged field by field, recursively
+    /// For any other pair of values the right one replaces the left one, arrays aren't merged
+    Merge,
+}
+
+impl BinopKind {
+    /// The operator as it's written
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinopKind::Add => "+",
+            BinopKind::Sub => "-",
+            BinopKind::Mul => "*",
+            BinopKind::Div => "/",
+            BinopKind::Rem => "%",
+            BinopKind::Pow => "**",
+            BinopKind::Merge => "&",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -67,19 +145,73 @@ pub struct Binop {
 
 #[derive(Debug, Clone)]
 pub struct ImportDecl {
-    pub name: SmolStr,
+    pub name: Spanned<SmolStr>,
     pub path: SmolStr,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FnDecl {
     pub name: Spanned<SmolStr>,
     pub params: Spanned<FnParams>,
+    /// Annotated return type, `fn f() -> int { 1 }`
+    pub ret_ty: Option<Spanned<TypeExpr>>,
     pub body: ExprIdx,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FnParams(pub Vec<FnParam>);
 
-#[derive(Debug)]
-pub struct FnParam(pub SmolStr);
+#[derive(Debug, Clone)]
+pub struct FnParam {
+    pub name: Spanned<SmolStr>,
+    /// Annotated type, `fn f(x: int) { x }`
+    pub ty: Option<Spanned<TypeExpr>>,
+    /// Value used when no argument is passed, `fn f(x = 1) { x }`
+    /// It's evaluated where the function is declared, the other parameters aren't in scope
+    pub default: Option<ExprIdx>,
+    /// `fn f(...items) { items }`, the positional arguments left after the other parameters are
+    /// collected into an array, only the last parameter can be variadic
+    pub variadic: bool,
+}
+
+/// `schema Project { name: string, topics: [Topic] }`
+#[derive(Debug, Clone)]
+pub struct SchemaDecl {
+    pub name: Spanned<SmolStr>,
+    pub fields: Vec<(Spanned<SmolStr>, Spanned<TypeExpr>)>,
+}
+
+/// Type written in an annotation
+#[derive(Debug, Clone)]
+pub enum TypeExpr {
+    /// `int`, `Project`, `utils.Project`, ...
+    Named(Vec<SmolStr>),
+    /// `[int]`
+    Array(Box<Spanned<TypeExpr>>),
+    /// `{ name: string, stars: int }`
+    Record(Vec<(Spanned<SmolStr>, Spanned<TypeExpr>)>),
+    /// `string?`, either `null` or a string, record fields of this type can be left out
+    Optional(Box<Spanned<TypeExpr>>),
+    Error,
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeExpr::Named(path) => write!(f, "{}", path.join(".")),
+            TypeExpr::Array(el) => write!(f, "[{}]", el.inner),
+            TypeExpr::Record(fields) => {
+                write!(f, "{{ ")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", k.inner, v.inner)?;
+                }
+                write!(f, " }}")
+            }
+            TypeExpr::Optional(ty) => write!(f, "{}?", ty.inner),
+            TypeExpr::Error => write!(f, "{{error}}"),
+        }
+    }
+}
@@ -1,6 +1,4 @@
-use smol_str::SmolStr;
-
-use crate::ast::{ImportDecl, Spanned};
+use crate::ast::{ImportDecl, SchemaDecl, Spanned};
 
 use super::*;
 
@@ -11,85 +9,157 @@ const FN_PARAMS_BEGIN_RECOVERY_SET: &[TokenKind] = &[TokenKind::RParen, TokenKin
 const FN_PARAMS_END_RECOVERY_SET: &[TokenKind] = &[TokenKind::LCurly];
 const FN_PARAMS_COMMA_RECOVERY_SET: &[TokenKind] = &[TokenKind::RParen, TokenKind::LCurly];
 const FN_PARAM_RECOVERY_SET: &[TokenKind] = &[TokenKind::Comma, TokenKind::RParen];
-const FN_LCURLY_RECOVERY_SET: &[TokenKind] = &[TokenKind::RCurly, TokenKind::Fn, TokenKind::Import];
+const FN_LCURLY_RECOVERY_SET: &[TokenKind] = &[
+    TokenKind::RCurly,
+    TokenKind::Fn,
+    TokenKind::Import,
+    TokenKind::Schema,
+];
+const SCHEMA_NAME_RECOVERY_SET: &[TokenKind] = &[TokenKind::LCurly];
 
 impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
     pub(super) fn top_level_decls(&mut self) -> Module {
         let mut imports = vec![];
         let mut functions = vec![];
+        let mut schemas = vec![];
         let mut expr = None;
+        let (mut import_names, mut fn_names, mut schema_names) = Default::default();
         while !self.at_end() {
             if self.at(TokenKind::Import) {
-                imports.push(self.import_decl());
+                let import = self.import_decl();
+                self.check_duplicate(&mut import_names, &import.name, "import");
+                imports.push(import);
             } else if self.at(TokenKind::Fn) {
-                functions.push(self.fn_decl());
+                let function = self.fn_decl();
+                self.check_duplicate(&mut fn_names, &function.name, "function");
+                functions.push(function);
+            } else if self.at(TokenKind::Schema) {
+                let schema = self.schema_decl();
+                self.check_duplicate(&mut schema_names, &schema.name, "schema");
+                schemas.push(schema);
             } else {
                 if expr.is_some() {
-                    self.error(format!("awlyc files can only contain one expression"));
+                    self.error("awlyc files can only contain one expression".to_string());
                     continue;
                 }
                 expr = Some(self.expr());
             }
         }
         Module {
+            file_id: self.file_id.clone(),
             imports,
             functions,
+            schemas,
             expr,
         }
     }
 
     fn import_decl(&mut self) -> ImportDecl {
+        self.enter_context("import");
         self.expect(TokenKind::Import, &[]);
-        let name = self
-            .expect(TokenKind::Ident, IMPORT_NAME_RECOVERY_SET)
-            .unwrap()
-            .text
-            .into();
-        let path: SmolStr = self
-            .expect(TokenKind::StringLit, IMPORT_PATH_RECOVERY_SET)
-            .unwrap()
-            .text
-            .into();
-        let path = &path[1..path.len() - 1]; // TODO: this should probably be handled during lexing
-        ImportDecl {
-            name,
-            path: SmolStr::from(path),
-        }
+        let name = self.ident(IMPORT_NAME_RECOVERY_SET);
+        let path = match self.expect(TokenKind::StringLit, IMPORT_PATH_RECOVERY_SET) {
+            Some(path) if path.kind == TokenKind::StringLit => self.string_lit(&path),
+            _ => SmolStr::default(),
+        };
+        self.exit_context();
+        ImportDecl { name, path }
+    }
+
+    fn schema_decl(&mut self) -> SchemaDecl {
+        self.enter_context("schema");
+        self.expect(TokenKind::Schema, &[]);
+        let name = self.ident(SCHEMA_NAME_RECOVERY_SET);
+        let fields = if self.at(TokenKind::LCurly) {
+            self.record_type()
+        } else {
+            self.error_expected();
+            vec![]
+        };
+        self.exit_context();
+        SchemaDecl { name, fields }
     }
 
     fn fn_decl(&mut self) -> FnDecl {
+        self.enter_context("function");
         self.expect(TokenKind::Fn, &[]);
-        let name = self.expect(TokenKind::Ident, FN_NAME_RECOVERY_SET).unwrap();
-        let name = Spanned {
-            inner: name.text,
-            span: Span {
-                range: name.range,
-                file_id: self.file_id.clone(),
-            },
-        };
+        let name = self.ident(FN_NAME_RECOVERY_SET);
         let params = self.fn_params();
+        let ret_ty = if self.at(TokenKind::Arrow) {
+            self.next();
+            Some(self.type_expr())
+        } else {
+            None
+        };
+        let body = self.fn_body();
+        self.exit_context();
+        FnDecl {
+            name,
+            params,
+            ret_ty,
+            body,
+        }
+    }
+
+    /// `: expr`, `{ expr }` or a record literal, the first two only differ in how they're written
+    /// A `{` followed by `}`, `...`, `key:` or `key,` opens a record literal, any other `{` a braced
+    /// body
+    fn fn_body(&mut self) -> ExprIdx {
+        if self.at(TokenKind::Colon) {
+            self.next();
+            return self.line_expr();
+        }
+        if self.at_record_body() {
+            let record = self.record_expr();
+            return self.expr_arena.alloc(record);
+        }
         self.expect(TokenKind::LCurly, FN_LCURLY_RECOVERY_SET);
         let body = self.expr();
         self.expect(TokenKind::RCurly, FN_LCURLY_RECOVERY_SET);
-        FnDecl { name, params, body }
+        body
+    }
+
+    fn at_record_body(&mut self) -> bool {
+        if self.peek_kind() != Some(TokenKind::LCurly) {
+            return false;
+        }
+        let mut ahead = self.tokens.clone().skip(1).map(|tok| tok.kind);
+        matches!(
+            (ahead.next(), ahead.next()),
+            (Some(TokenKind::RCurly | TokenKind::Ellipsis), _)
+                | (
+                    Some(TokenKind::Ident | TokenKind::StringLit),
+                    Some(TokenKind::Colon)
+                )
+                | (Some(TokenKind::Ident), Some(TokenKind::Comma))
+        )
     }
 
     fn fn_params(&mut self) -> Spanned<FnParams> {
         let mut params = vec![];
+        let mut names = HashMap::new();
         let start = self.peek_range().start();
         self.expect(TokenKind::LParen, FN_PARAMS_BEGIN_RECOVERY_SET);
         while !self.at(TokenKind::RParen) && !self.at_end() {
-            params.push(self.fn_param());
+            let param = self.fn_param();
+            self.check_duplicate(&mut names, &param.name, "parameter");
+            if let Some(last) = params.last().filter(|last: &&FnParam| last.variadic) {
+                self.errors.push(Diagnostic {
+                    kind: DiagnosticKind::Error,
+                    msg: "only the last parameter can be variadic".to_string(),
+                    span: last.name.span.clone(),
+                    labels: vec![],
+                });
+            }
+            params.push(param);
             if !self.at(TokenKind::RParen) {
                 if !self.at(TokenKind::Comma) {
-                    self.error(format!(
-                        "expected either `,` or `)` in function parameter list"
-                    ));
+                    self.error_expected();
                     break;
                 } else {
                     self.next();
                     if self.at(TokenKind::RParen) {
-                        self.error(format!("expected identifier in function parameter list"));
+                        self.error("expected identifier in function parameter list".to_string());
                         while !self.at_set(FN_PARAMS_COMMA_RECOVERY_SET) {
                             self.next();
                         }
@@ -98,21 +168,44 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
             }
         }
         self.expect(TokenKind::RParen, FN_PARAMS_END_RECOVERY_SET);
-        let end = self.peek_range().end();
         Spanned {
             inner: FnParams(params),
-            span: Span {
-                range: TextRange::new(start, end),
-                file_id: self.file_id.clone(),
-            },
+            span: self.span_from(start),
         }
     }
 
     fn fn_param(&mut self) -> FnParam {
-        let name = self
-            .expect(TokenKind::Ident, FN_PARAM_RECOVERY_SET)
-            .unwrap()
-            .text;
-        FnParam(name)
+        let variadic = self.at(TokenKind::Ellipsis);
+        if variadic {
+            self.next();
+        }
+        let name = self.ident(FN_PARAM_RECOVERY_SET);
+        let ty = if self.at(TokenKind::Colon) {
+            self.next();
+            Some(self.type_expr())
+        } else {
+            None
+        };
+        let default = if self.at(TokenKind::Eq) {
+            self.next();
+            let default = self.expr();
+            if variadic {
+                self.errors.push(Diagnostic {
+                    kind: DiagnosticKind::Error,
+                    msg: "variadic parameters can't have a default value".to_string(),
+                    span: self.expr_arena[default].span.clone(),
+                    labels: vec![],
+                });
+            }
+            Some(default)
+        } else {
+            None
+        };
+        FnParam {
+            name,
+            ty,
+            default,
+            variadic,
+        }
     }
 }
@@ -5,7 +5,9 @@ use super::*;
 use smallvec::SmallVec;
 use smol_str::SmolStr;
 
-use crate::ast::{Binop, BinopKind, Call, Expr, ExprIdx, Negate, Record, Spanned};
+use crate::ast::{
+    Arg, Binop, BinopKind, Call, Expr, ExprIdx, Field, Index, Negate, Record, RecordEntry, Spanned,
+};
 
 // Catch expression, or the end of array
 // const ARRAY_COMMA_RECOVERY_SET: &[TokenKind] = &[
@@ -39,7 +41,12 @@ const RECORD_COLON_RECOVERY_SET: &[TokenKind] = &[
     TokenKind::LCurly,
     TokenKind::RCurly,
 ];
-const RECORD_COMMA_RECOVERY_SET: &[TokenKind] = &[TokenKind::Ident, TokenKind::RCurly];
+const RECORD_COMMA_RECOVERY_SET: &[TokenKind] = &[
+    TokenKind::Ident,
+    TokenKind::StringLit,
+    TokenKind::Ellipsis,
+    TokenKind::RCurly,
+];
 const RECORD_CLOSE_BRACKET_RECOVERY_SET: &[TokenKind] = GLOBAL_RECOVERY_SET;
 const CALL_OPEN_PAREN_RECOVERY_SET: &[TokenKind] = &[
     TokenKind::Ident,
@@ -54,21 +61,96 @@ const CALL_OPEN_PAREN_RECOVERY_SET: &[TokenKind] = &[
 const CALL_ARGS_COMMA_RECOVERY_SET: &[TokenKind] = CALL_OPEN_PAREN_RECOVERY_SET;
 const CALL_CLOSE_PAREN_RECOVERY_SET: &[TokenKind] = &[TokenKind::RCurly, TokenKind::RSquare]; // not rlly sure what to put for the following two. maybe global recovery set but i think that'd skip way too much
 const PATH_RECOVERY_SET: &[TokenKind] = &[TokenKind::RCurly, TokenKind::RSquare];
+const FIELD_RECOVERY_SET: &[TokenKind] = PATH_RECOVERY_SET;
+const INDEX_CLOSE_BRACKET_RECOVERY_SET: &[TokenKind] = CALL_CLOSE_PAREN_RECOVERY_SET;
+const PAREN_CLOSE_RECOVERY_SET: &[TokenKind] = CALL_CLOSE_PAREN_RECOVERY_SET;
+
+/// Binding power of the operand of a prefix `-`
+/// Only `**` binds tighter, `-2 ** 2` is `-(2 ** 2)`
+const PREFIX_BP: u8 = 7;
+
+/// Binary operator of a token, with its left and right binding powers
+/// A higher binding power binds tighter, operators whose left binding power is the lower one are
+/// left associative
+fn infix_binding_power(kind: TokenKind) -> Option<(BinopKind, u8, u8)> {
+    let op = match kind {
+        TokenKind::Amp => (BinopKind::Merge, 1, 2),
+        TokenKind::Plus => (BinopKind::Add, 3, 4),
+        TokenKind::Minus => (BinopKind::Sub, 3, 4),
+        TokenKind::Star => (BinopKind::Mul, 5, 6),
+        TokenKind::FSlah => (BinopKind::Div, 5, 6),
+        TokenKind::Percent => (BinopKind::Rem, 5, 6),
+        TokenKind::StarStar => (BinopKind::Pow, 10, 9),
+        _ => return None,
+    };
+    Some(op)
+}
 
 impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
     pub(crate) fn expr(&mut self) -> ExprIdx {
-        let lhs = self.primary_expr();
-        let expr = self.binop_rhs(0, lhs);
-        self.postfix(expr)
+        let line_ends_postfix = std::mem::replace(&mut self.line_ends_postfix, false);
+        let expr = self.expr_bp(0);
+        self.line_ends_postfix = line_ends_postfix;
+        expr
+    }
+
+    /// Expression that ends at a line break before a `(` or `[`, so the body of `fn f(): x` doesn't
+    /// call or index a file expression on the next line
+    pub(crate) fn line_expr(&mut self) -> ExprIdx {
+        self.line_ends_postfix = true;
+        let expr = self.expr_bp(0);
+        self.line_ends_postfix = false;
+        expr
+    }
+
+    /// Expression made of operators that bind at least as tightly as `min_bp`
+    fn expr_bp(&mut self, min_bp: u8) -> ExprIdx {
+        if let Err(span) = self.enter_nested() {
+            return self.expr_arena.alloc(Spanned {
+                inner: Expr::Error,
+                span,
+            });
+        }
+        let expr = self.expr_bp_inner(min_bp);
+        self.exit_nested();
+        expr
+    }
+
+    fn expr_bp_inner(&mut self, min_bp: u8) -> ExprIdx {
+        let mut lhs = if self.at(TokenKind::Minus) {
+            let negate = self.negate_expr();
+            self.expr_arena.alloc(negate)
+        } else {
+            self.primary_expr()
+        };
+        loop {
+            let (op, l_bp, r_bp) = match self.peek_kind().and_then(infix_binding_power) {
+                Some(op) => op,
+                None => return lhs,
+            };
+            if l_bp < min_bp {
+                return lhs;
+            }
+            self.next();
+            let rhs = self.expr_bp(r_bp);
+            let span = Span::combine(&self.expr_arena[lhs].span, &self.expr_arena[rhs].span);
+            lhs = self.expr_arena.alloc(Spanned {
+                inner: Expr::Binop(Binop { lhs, op, rhs }),
+                span,
+            });
+        }
     }
 
+    /// Operand of an operator, with its calls, field accesses and indexing
     fn primary_expr(&mut self) -> ExprIdx {
+        if self.at(TokenKind::LParen) {
+            let expr = self.paren_expr();
+            return self.postfix(expr);
+        }
         let expr = if self.at(TokenKind::Ident) {
             self.path_expr()
         } else if self.at(TokenKind::IntLit) {
             self.int_expr()
-        } else if self.at(TokenKind::Minus) {
-            self.negate_expr()
         } else if self.at(TokenKind::FloatLit) {
             self.float_lit()
         } else if self.at(TokenKind::StringLit) {
@@ -79,97 +161,117 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
             self.record_expr()
         } else if self.at(TokenKind::Null) {
             self.null_expr()
+        } else if self.at(TokenKind::True) || self.at(TokenKind::False) {
+            self.bool_expr()
         } else {
-            self.error(format!("expected expression"));
+            // the unexpected token is skipped
+            let range = self.peek_range();
+            self.error_expected();
             Spanned {
                 inner: Expr::Error,
                 span: Span {
-                    range: self.peek_range(),
+                    range,
                     file_id: self.file_id.clone(),
                 },
             }
         };
-        self.expr_arena.alloc(expr)
+        let expr = self.expr_arena.alloc(expr);
+        self.postfix(expr)
     }
 
-    fn binop_rhs(&mut self, expr_prec: i32, mut lhs: ExprIdx) -> ExprIdx {
+    /// Calls, field accesses and indexing, `utils.project("x").tags[0]`
+    fn postfix(&mut self, mut expr: ExprIdx) -> ExprIdx {
+        // `a.b(..)` calls the function `b` of the module imported as `a`, otherwise `a.b` is the
+        // field `b` of `a`
+        if self.peek_kind() != Some(TokenKind::LParen) || self.at_line_break() {
+            expr = self.path_fields(expr);
+        }
+        // like operators, these are optional and aren't listed when something else is expected
         loop {
-            let tok_prec = self.tok_prec();
-
-            if tok_prec < expr_prec {
-                return lhs;
-            }
-
-            let binop = self.peek().unwrap().kind;
-            self.next();
+            let postfix = match self.peek_kind() {
+                Some(TokenKind::LParen | TokenKind::LSquare) if self.at_line_break() => {
+                    return expr
+                }
+                Some(TokenKind::LParen) => self.call_expr(expr),
+                Some(TokenKind::Period) => self.field_expr(expr),
+                Some(TokenKind::LSquare) => self.index_expr(expr),
+                _ => return expr,
+            };
+            expr = self.expr_arena.alloc(postfix);
+        }
+    }
 
-            let mut rhs = self.primary_expr();
+    /// Whether a line break before the next token ends the expression, see `line_expr`
+    fn at_line_break(&mut self) -> bool {
+        let range = TextRange::new(self.last_end, self.peek_range().start());
+        self.line_ends_postfix && self.src[range].contains('\n')
+    }
 
-            let next_prec = self.tok_prec();
-            if tok_prec < next_prec {
-                rhs = self.binop_rhs(tok_prec + 1, rhs.clone());
-            }
-            let end = self.peek_range().end();
+    /// Turn the path `a.b.c` into accesses of the fields `b` and `c` of `a`
+    fn path_fields(&mut self, expr: ExprIdx) -> ExprIdx {
+        let mut path = match &mut self.expr_arena[expr].inner {
+            Expr::Path(path) if path.len() > 1 => std::mem::take(path),
+            _ => return expr,
+        };
+        let fields = path.split_off(1);
+        let first = path[0].span.clone();
+        self.expr_arena[expr] = Spanned {
+            inner: Expr::Path(path),
+            span: first.clone(),
+        };
+        fields.into_iter().fold(expr, |expr, field| {
+            let span = Span::combine(&first, &field.span);
+            self.expr_arena.alloc(Spanned {
+                inner: Expr::Field(Field { expr, field }),
+                span,
+            })
+        })
+    }
 
-            lhs = self.expr_arena.alloc(Spanned {
-                inner: Expr::Binop(Binop {
-                    lhs,
-                    op: match binop {
-                        TokenKind::Plus => BinopKind::Add,
-                        TokenKind::Minus => BinopKind::Sub,
-                        TokenKind::Star => BinopKind::Mul,
-                        TokenKind::FSlah => BinopKind::Div,
-                        _ => unreachable!(),
+    fn field_expr(&mut self, expr: ExprIdx) -> Spanned<Expr> {
+        let start = self.expr_arena[expr].span.range.start();
+        self.expect(TokenKind::Period, &[]);
+        let (text, range) = match self.expect(TokenKind::Ident, FIELD_RECOVERY_SET) {
+            Some(field) if field.kind == TokenKind::Ident => (field.text, field.range),
+            // the error has been reported, the field's name doesn't matter anymore
+            _ => (SmolStr::default(), self.peek_range()),
+        };
+        Spanned {
+            inner: Expr::Field(Field {
+                expr,
+                field: Spanned {
+                    inner: text,
+                    span: Span {
+                        range,
+                        file_id: self.file_id.clone(),
                     },
-                    rhs,
-                }),
-                span: Span {
-                    range: TextRange::new(self.expr_arena[lhs].span.range.end(), end),
-                    file_id: self.file_id.clone(),
                 },
-            });
+            }),
+            span: self.span_from(start),
         }
     }
 
-    fn postfix(&mut self, expr: ExprIdx) -> ExprIdx {
-        if self.at(TokenKind::LParen) {
-            let call = self.call_expr(expr);
-            self.expr_arena.alloc(call)
-        } else {
-            expr
+    fn index_expr(&mut self, expr: ExprIdx) -> Spanned<Expr> {
+        let start = self.expr_arena[expr].span.range.start();
+        self.expect(TokenKind::LSquare, &[]);
+        let index = self.expr();
+        self.expect(TokenKind::RSquare, INDEX_CLOSE_BRACKET_RECOVERY_SET);
+        Spanned {
+            inner: Expr::Index(Index { expr, index }),
+            span: self.span_from(start),
         }
     }
 
     fn path_expr(&mut self) -> Spanned<Expr> {
         let start = self.peek_range().start();
-        let mut parts = vec![];
-        let part = self.expect(TokenKind::Ident, &[]).unwrap();
-        parts.push(Spanned {
-            inner: part.text,
-            span: Span {
-                range: part.range,
-                file_id: self.file_id.clone(),
-            },
-        });
-
-        while self.at(TokenKind::Period) {
+        let mut parts = vec![self.ident(&[])];
+        while self.peek_kind() == Some(TokenKind::Period) {
             self.next();
-            let part = self.expect(TokenKind::Ident, PATH_RECOVERY_SET).unwrap();
-            parts.push(Spanned {
-                inner: part.text,
-                span: Span {
-                    range: part.range,
-                    file_id: self.file_id.clone(),
-                },
-            });
+            parts.push(self.ident(PATH_RECOVERY_SET));
         }
-        let end = self.peek_range().end();
         Spanned {
             inner: Expr::Path(parts),
-            span: Span {
-                range: TextRange::new(start, end),
-                file_id: self.file_id.clone(),
-            },
+            span: self.span_from(start),
         }
     }
 
@@ -186,23 +288,15 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
         let n = u64::from_str_radix(&text, radix);
         if let Some(err) = n.as_ref().err() {
             self.error(format!("could not parse integer: {}", err));
-            let end = self.peek_range().end();
             Spanned {
                 inner: Expr::Error,
-                span: Span {
-                    range: TextRange::new(start, end),
-                    file_id: self.file_id.clone(),
-                },
+                span: self.span_from(start),
             }
         } else {
             self.next();
-            let end = self.peek_range().end();
             Spanned {
                 inner: Expr::Int(n.unwrap()),
-                span: Span {
-                    range: TextRange::new(start, end),
-                    file_id: self.file_id.clone(),
-                },
+                span: self.span_from(start),
             }
         }
     }
@@ -212,64 +306,61 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
         let n: Result<f64, _> = self.peek().unwrap().text.parse();
         if let Some(err) = n.as_ref().err() {
             self.error(format!("could not parse float: {}", err));
-            let end = self.peek_range().end();
             Spanned {
                 inner: Expr::Error,
-                span: Span {
-                    range: TextRange::new(start, end),
-                    file_id: self.file_id.clone(),
-                },
+                span: self.span_from(start),
             }
         } else {
             self.next();
-            let end = self.peek_range().end();
             Spanned {
                 inner: Expr::Float(n.unwrap()),
-                span: Span {
-                    range: TextRange::new(start, end),
-                    file_id: self.file_id.clone(),
-                },
+                span: self.span_from(start),
             }
         }
     }
 
     fn string_expr(&mut self) -> Spanned<Expr> {
         let start = self.peek_range().start();
-        let content = self.expect(TokenKind::StringLit, &[]).unwrap().text;
-        let content = &content[1..content.len() - 1];
-        let end = self.peek_range().end();
+        let tok = self.expect(TokenKind::StringLit, &[]).unwrap();
+        let content = self.string_lit(&tok);
         Spanned {
-            inner: Expr::String(SmolStr::from(content)),
-            span: Span {
-                range: TextRange::new(start, end),
-                file_id: self.file_id.clone(),
-            },
+            inner: Expr::String(content),
+            span: self.span_from(start),
         }
     }
 
+    /// `(expr)`, only changes how operators are grouped
+    /// The span of the grouped expression is widened to the parentheses, so that the spans of
+    /// operations it's an operand of don't start or end in the middle of them
+    fn paren_expr(&mut self) -> ExprIdx {
+        let start = self.peek_range().start();
+        self.expect(TokenKind::LParen, &[]);
+        let expr = self.expr();
+        self.expect(TokenKind::RParen, PAREN_CLOSE_RECOVERY_SET);
+        self.expr_arena[expr].span = self.span_from(start);
+        expr
+    }
+
     fn negate_expr(&mut self) -> Spanned<Expr> {
         let start = self.peek_range().start();
         self.expect(TokenKind::Minus, &[]);
-        let expr = self.expr();
-        let end = self.peek_range().end();
+        let expr = self.expr_bp(PREFIX_BP);
         Spanned {
             inner: Expr::Negate(Negate { expr }),
-            span: Span {
-                range: TextRange::new(start, end),
-                file_id: self.file_id.clone(),
-            },
+            span: self.span_from(start),
         }
     }
 
     fn array_expr(&mut self) -> Spanned<Expr> {
         let start = self.peek_range().start();
         let mut exprs = SmallVec::new();
+        self.enter_context("array");
         self.expect(TokenKind::LSquare, &[]); // we checked that this was an LSquare before entering this function, so no recover set needed
         while !self.at(TokenKind::RSquare) && !self.at_end() {
             exprs.push(self.expr());
             if !self.at(TokenKind::RSquare) {
                 if !self.at(TokenKind::Comma) {
-                    self.error(format!("expected either `,` or `]` in array"));
+                    self.error_expected();
                     break;
                 } else {
                     self.next();
@@ -277,43 +368,56 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
             }
         }
         self.expect(TokenKind::RSquare, ARRAY_CLOSE_BRACKET_RECOVERY_SET);
-        let end = self.peek_range().end();
+        self.exit_context();
         Spanned {
             inner: Expr::Array(exprs),
-            span: Span {
-                range: TextRange::new(start, end),
-                file_id: self.file_id.clone(),
-            },
+            span: self.span_from(start),
         }
     }
 
-    fn record_expr(&mut self) -> Spanned<Expr> {
+    pub(crate) fn record_expr(&mut self) -> Spanned<Expr> {
         let start = self.peek_range().start();
-        let mut fields: HashMap<SmolStr, ExprIdx> = HashMap::new();
+        let mut entries = vec![];
+        let mut keys = HashMap::new();
+        self.enter_context("record");
         self.expect(TokenKind::LCurly, &[]); // see comment in array_expr
         while !self.at(TokenKind::RCurly) && !self.at_end() {
-            let key = self
-                .expect(TokenKind::Ident, RECORD_KEY_RECOVERY_SET)
-                .unwrap();
-            let key = Spanned {
-                inner: key.text,
-                span: Span {
-                    range: key.range,
-                    file_id: self.file_id.clone(),
+            if self.at(TokenKind::Ellipsis) {
+                self.next();
+                entries.push(RecordEntry::Spread(self.expr()));
+                if !self.at(TokenKind::RCurly) {
+                    self.expect(TokenKind::Comma, RECORD_COMMA_RECOVERY_SET);
+                }
+                continue;
+            }
+            // keys that aren't identifiers are quoted
+            let quoted = self.at(TokenKind::StringLit);
+            let quoted_key = if quoted { self.next() } else { None };
+            let key = match quoted_key {
+                Some(key) => Spanned {
+                    inner: self.string_lit(&key),
+                    span: Span {
+                        range: key.range,
+                        file_id: self.file_id.clone(),
+                    },
                 },
+                None => self.ident(RECORD_KEY_RECOVERY_SET),
             };
-            if self.at(TokenKind::Comma) {
+            // the first of duplicate fields is kept
+            let first = !keys.contains_key(&key.inner);
+            self.check_duplicate(&mut keys, &key, "key");
+            if !quoted && self.at(TokenKind::Comma) {
                 self.next();
-                fields.insert(
-                    key.inner.clone(),
-                    self.expr_arena.alloc(Spanned {
-                        inner: Expr::Path(vec![Spanned {
-                            inner: key.inner.clone(),
-                            span: key.span.clone(), // this literally makes no sense but for some reason i cant just do key.clone()...
-                        }]),
-                        span: key.span.clone(),
-                    }),
-                );
+                let value = self.expr_arena.alloc(Spanned {
+                    inner: Expr::Path(vec![Spanned {
+                        inner: key.inner.clone(),
+                        span: key.span.clone(), // this literally makes no sense but for some reason i cant just do key.clone()...
+                    }]),
+                    span: key.span.clone(),
+                });
+                if first {
+                    entries.push(RecordEntry::Field(key.inner, value));
+                }
                 continue;
             }
             self.expect(TokenKind::Colon, RECORD_COLON_RECOVERY_SET);
@@ -322,16 +426,15 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
             if !self.at(TokenKind::RCurly) {
                 self.expect(TokenKind::Comma, RECORD_COMMA_RECOVERY_SET);
             }
-            fields.insert(key.inner, value);
+            if first {
+                entries.push(RecordEntry::Field(key.inner, value));
+            }
         }
         self.expect(TokenKind::RCurly, RECORD_CLOSE_BRACKET_RECOVERY_SET);
-        let end = self.peek_range().end();
+        self.exit_context();
         Spanned {
-            inner: Expr::Record(Record(fields)),
-            span: Span {
-                range: TextRange::new(start, end),
-                file_id: self.file_id.clone(),
-            },
+            inner: Expr::Record(Record { entries }),
+            span: self.span_from(start),
         }
     }
 
@@ -339,43 +442,81 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
         let start = self.expr_arena[callee].span.range.start();
         let args_start = self.peek_range().start();
         let mut args = vec![];
+        // the call starts at its callee, not where its arguments do
+        self.contexts.push((start, "call"));
         self.expect(TokenKind::LParen, CALL_OPEN_PAREN_RECOVERY_SET);
         while !self.at(TokenKind::RParen) && !self.at_end() {
-            args.push(self.expr());
+            let arg = self.call_arg();
+            let after_named = args.last().is_some_and(|arg: &Arg| arg.name.is_some());
+            if arg.name.is_none() && after_named {
+                self.errors.push(Diagnostic {
+                    kind: DiagnosticKind::Error,
+                    msg: "positional arguments can't follow named arguments".to_string(),
+                    span: self.expr_arena[arg.value].span.clone(),
+                    labels: vec![],
+                });
+            }
+            args.push(arg);
             if !self.at(TokenKind::RParen) {
                 self.expect(TokenKind::Comma, CALL_ARGS_COMMA_RECOVERY_SET);
             }
         }
         self.expect(TokenKind::RParen, CALL_CLOSE_PAREN_RECOVERY_SET);
-        let end = self.peek_range().end();
+        self.exit_context();
         Spanned {
             inner: Expr::Call(Call {
                 callee,
                 args: Spanned {
                     inner: args,
-                    span: Span {
-                        range: TextRange::new(args_start, end),
-                        file_id: self.file_id.clone(),
-                    },
+                    span: self.span_from(args_start),
                 },
             }),
-            span: Span {
-                range: TextRange::new(start, end),
-                file_id: self.file_id.clone(),
-            },
+            span: self.span_from(start),
+        }
+    }
+
+    /// `x`, `name: x` or `...xs`
+    fn call_arg(&mut self) -> Arg {
+        if self.at(TokenKind::Ellipsis) {
+            self.next();
+            return Arg {
+                name: None,
+                value: self.expr(),
+                spread: true,
+            };
+        }
+        let mut lookahead = self.tokens.clone().map(|tok| tok.kind);
+        let named = lookahead.next() == Some(TokenKind::Ident)
+            && lookahead.next() == Some(TokenKind::Colon);
+        let name = if named {
+            let name = self.ident(&[]);
+            self.next();
+            Some(name)
+        } else {
+            None
+        };
+        Arg {
+            name,
+            value: self.expr(),
+            spread: false,
+        }
+    }
+
+    fn bool_expr(&mut self) -> Spanned<Expr> {
+        let start = self.peek_range().start();
+        let value = self.next().unwrap().kind == TokenKind::True;
+        Spanned {
+            inner: Expr::Bool(value),
+            span: self.span_from(start),
         }
     }
 
     fn null_expr(&mut self) -> Spanned<Expr> {
         let start = self.peek_range().start();
         self.next();
-        let end = self.peek_range().end();
         Spanned {
             inner: Expr::Null,
-            span: Span {
-                range: TextRange::new(start, end),
-                file_id: self.file_id.clone(),
-            },
+            span: self.span_from(start),
         }
     }
 }
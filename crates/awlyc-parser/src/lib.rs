@@ -1,29 +1,72 @@
-use std::iter::Peekable;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    iter::Peekable,
+};
 
-use ast::{Expr, ExprIdx, FnDecl, FnParam, FnParams, ImportDecl, Spanned};
+use ast::{Expr, ExprIdx, FnDecl, FnParam, FnParams, ImportDecl, SchemaDecl, Spanned};
 use awlyc_error::{Diagnostic, DiagnosticKind, FileId, Span};
 use awlyc_lexer::{lex, Token, TokenKind};
 use la_arena::Arena;
-use text_size::TextRange;
+use smol_str::SmolStr;
+use text_size::{TextRange, TextSize};
 
 pub mod ast;
 mod decl;
 mod expr;
+mod ty;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Module {
+    /// File the module was parsed from, imports are resolved relative to it
+    pub file_id: FileId,
     pub imports: Vec<ImportDecl>,
     pub functions: Vec<FnDecl>,
+    pub schemas: Vec<SchemaDecl>,
     pub expr: Option<ExprIdx>,
 }
 
-const GLOBAL_RECOVERY_SET: &[TokenKind] = &[TokenKind::Fn, TokenKind::Import];
+const GLOBAL_RECOVERY_SET: &[TokenKind] = &[TokenKind::Fn, TokenKind::Import, TokenKind::Schema];
+
+/// Tokens an expression can start with, when all of them are expected they're reported as one
+const EXPR_START: &[TokenKind] = &[
+    TokenKind::Minus,
+    TokenKind::LParen,
+    TokenKind::Ident,
+    TokenKind::IntLit,
+    TokenKind::FloatLit,
+    TokenKind::StringLit,
+    TokenKind::LSquare,
+    TokenKind::LCurly,
+    TokenKind::Null,
+    TokenKind::True,
+    TokenKind::False,
+];
+/// Tokens a type can start with
+const TYPE_START: &[TokenKind] = &[
+    TokenKind::Null,
+    TokenKind::Ident,
+    TokenKind::LSquare,
+    TokenKind::LCurly,
+];
+
+/// Expressions and types can't be nested deeper than this, it keeps everything that recurses over
+/// them from overflowing the stack
+const MAX_NESTING: usize = 64;
 
 struct Parser<'src, I: Iterator<Item = Token> + Clone> {
     tokens: Peekable<I>,
     errors: Vec<Diagnostic>,
     /// Token kinds we expect to find are stored here to be displayed in error diagnostics
     expected_tokens: Vec<TokenKind>,
+    /// End of the last consumed token, where the node being parsed ends
+    last_end: TextSize,
+    /// Expressions and types being parsed, one inside the other
+    nesting: usize,
+    /// Start and kind of the constructs being parsed, syntax errors point at the innermost one
+    contexts: Vec<(TextSize, &'static str)>,
+    /// `(` and `[` on a new line start the next expression instead of calling or indexing the
+    /// previous one, outside of any brackets in a colon body
+    line_ends_postfix: bool,
     expr_arena: &'src mut Arena<Spanned<Expr>>,
     file_id: FileId,
     src: &'src str,
@@ -40,6 +83,10 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
             tokens,
             errors: vec![],
             expected_tokens: vec![],
+            last_end: 0.into(),
+            nesting: 0,
+            contexts: vec![],
+            line_ends_postfix: false,
             expr_arena,
             src,
             file_id,
@@ -48,7 +95,22 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
 
     fn next(&mut self) -> Option<Token> {
         self.expected_tokens.clear();
-        self.tokens.next()
+        let tok = self.tokens.next();
+        if let Some(tok) = &tok {
+            self.last_end = tok.range.end();
+        }
+        tok
+    }
+
+    /// Span from `start`, the start of the first token of a node, to the end of the last consumed
+    /// token
+    fn span_from(&self, start: TextSize) -> Span {
+        // nothing has been consumed since `start` if the node is missing at the end of the input
+        let end = self.last_end.max(start);
+        Span {
+            range: TextRange::new(start, end),
+            file_id: self.file_id.clone(),
+        }
     }
 
     fn expect(&mut self, kind: TokenKind, recovery_set: &[TokenKind]) -> Option<Token> {
@@ -56,15 +118,7 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
         if self.at(kind) {
             self.next();
         } else {
-            self.error(format!(
-                "expected `{}`",
-                self.expected_tokens
-                    .iter()
-                    .map(|kind| format!("{:?}", kind)) // TODO: impl Display
-                    .reduce(|s, kind| format!("{}, {}", s, kind))
-                    .unwrap()
-            ));
-
+            self.error_expected();
             while !self.at_set(recovery_set) && !self.at_end() {
                 self.next();
             }
@@ -72,8 +126,175 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
         tok
     }
 
+    /// Report that none of the expected tokens were found, and skip the unexpected one
+    fn error_expected(&mut self) {
+        let mut expected = vec![];
+        for kind in &self.expected_tokens {
+            let class = [("expression", EXPR_START), ("type", TYPE_START)]
+                .into_iter()
+                .find(|(_, class)| {
+                    class.contains(kind) && class.iter().all(|k| self.expected_tokens.contains(k))
+                });
+            let name = match class {
+                Some((name, _)) => name.to_string(),
+                None => kind.to_string(),
+            };
+            if !expected.contains(&name) {
+                expected.push(name);
+            }
+        }
+        let msg = match expected.split_last() {
+            Some((last, [])) => format!("expected {}", last),
+            Some((last, rest)) => format!("expected {} or {}", rest.join(", "), last),
+            None => "unexpected token".to_string(),
+        };
+        self.error(msg);
+    }
+
+    /// Start parsing a construct, syntax errors until `exit_context` are labelled with `what` it is
+    fn enter_context(&mut self, what: &'static str) {
+        let start = self.peek_range().start();
+        self.contexts.push((start, what));
+    }
+
+    fn exit_context(&mut self) {
+        self.contexts.pop();
+    }
+
+    /// Identifier expected next, a missing one is reported and replaced by an empty name where it
+    /// was expected
+    fn ident(&mut self, recovery_set: &[TokenKind]) -> Spanned<SmolStr> {
+        let range = self.peek_range();
+        let (text, range) = match self.expect(TokenKind::Ident, recovery_set) {
+            Some(tok) if tok.kind == TokenKind::Ident => (tok.text, tok.range),
+            _ => (SmolStr::default(), range),
+        };
+        Spanned {
+            inner: text,
+            span: Span {
+                range,
+                file_id: self.file_id.clone(),
+            },
+        }
+    }
+
+    /// Enter a nested expression or type
+    /// One that is nested too deeply is reported and skipped instead, the error has its span
+    fn enter_nested(&mut self) -> Result<(), Span> {
+        if self.nesting < MAX_NESTING {
+            self.nesting += 1;
+            return Ok(());
+        }
+        let start = self.peek_range().start();
+        // skip up to the end of the innermost delimited group, or of the token if it's not one
+        let mut depth = 0;
+        while let Some(kind) = self.peek_kind() {
+            match kind {
+                TokenKind::LParen | TokenKind::LCurly | TokenKind::LSquare => depth += 1,
+                TokenKind::RParen | TokenKind::RCurly | TokenKind::RSquare if depth == 0 => break,
+                TokenKind::RParen | TokenKind::RCurly | TokenKind::RSquare => depth -= 1,
+                _ => {}
+            }
+            self.next();
+            if depth == 0 {
+                break;
+            }
+        }
+        let span = self.span_from(start);
+        self.errors.push(Diagnostic {
+            kind: DiagnosticKind::Error,
+            msg: format!(
+                "nested too deeply, at most {} levels are allowed",
+                MAX_NESTING
+            ),
+            span: span.clone(),
+            labels: vec![],
+        });
+        Err(span)
+    }
+
+    fn exit_nested(&mut self) {
+        self.nesting -= 1;
+    }
+
+    /// Contents of a string literal with its escape sequences replaced
+    fn string_lit(&mut self, tok: &Token) -> SmolStr {
+        let content = &tok.text[1..tok.text.len() - 1];
+        let mut s = String::with_capacity(content.len());
+        let mut chars = content.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                s.push(c);
+                continue;
+            }
+            let escaped = match chars.next() {
+                Some('"') => Some('"'),
+                Some('\\') => Some('\\'),
+                Some('n') => Some('\n'),
+                Some('r') => Some('\r'),
+                Some('t') => Some('\t'),
+                Some('0') => Some('\0'),
+                Some('u') if chars.as_str().starts_with('{') => unicode_escape(&mut chars),
+                // any other backslash is kept as written, e.g. in `"C:\Users"` or `"\d+"`
+                other => {
+                    s.push('\\');
+                    s.extend(other);
+                    continue;
+                }
+            };
+            match escaped {
+                Some(c) => s.push(c),
+                None => self.errors.push(Diagnostic {
+                    kind: DiagnosticKind::Error,
+                    msg: "invalid escape sequence in string".to_string(),
+                    span: Span {
+                        range: tok.range,
+                        file_id: self.file_id.clone(),
+                    },
+                    labels: vec![],
+                }),
+            }
+        }
+        SmolStr::from(s)
+    }
+
+    /// Report `name` if it's already in `seen`, which maps the names of a kind of item to where
+    /// they're first used, `what` is that kind, e.g. `parameter`
+    fn check_duplicate(
+        &mut self,
+        seen: &mut HashMap<SmolStr, Span>,
+        name: &Spanned<SmolStr>,
+        what: &str,
+    ) {
+        // missing names have been reported
+        if name.inner.is_empty() {
+            return;
+        }
+        match seen.entry(name.inner.clone()) {
+            Entry::Occupied(first) => self.errors.push(Diagnostic {
+                kind: DiagnosticKind::Error,
+                msg: format!("duplicate {} `{}`", what, name.inner),
+                span: name.span.clone(),
+                labels: vec![(
+                    first.get().clone(),
+                    format!("`{}` first used here", name.inner),
+                )],
+            }),
+            Entry::Vacant(entry) => {
+                entry.insert(name.span.clone());
+            }
+        }
+    }
+
     fn error(&mut self, msg: String) {
         let range = self.peek_range();
+        let labels = match self.contexts.last() {
+            Some((start, what)) => vec![(
+                self.span_from(*start),
+                format!("while parsing this {}", what),
+            )],
+            None => vec![],
+        };
         self.errors.push(Diagnostic {
             kind: DiagnosticKind::Error,
             msg,
@@ -81,6 +302,7 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
                 range,
                 file_id: self.file_id.clone(),
             },
+            labels,
         });
 
         self.next();
@@ -119,27 +341,15 @@ impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
     }
 
     fn at(&mut self, kind: TokenKind) -> bool {
-        self.expected_tokens.push(kind);
+        if !self.expected_tokens.contains(&kind) {
+            self.expected_tokens.push(kind);
+        }
         self.peek_kind() == Some(kind)
     }
 
     #[inline]
     fn at_set(&mut self, set: &[TokenKind]) -> bool {
-        self.peek().map_or(false, |k| set.contains(&k.kind))
-    }
-
-    fn tok_prec(&mut self) -> i32 {
-        if let Some(tok) = self.peek() {
-            match tok.kind {
-                TokenKind::Plus => 10,
-                TokenKind::Minus => 10,
-                TokenKind::Star => 20,
-                TokenKind::FSlah => 20,
-                _ => -1,
-            }
-        } else {
-            -1
-        }
+        self.peek().is_some_and(|k| set.contains(&k.kind))
     }
 
     pub(crate) fn parse(&mut self) -> Module {
@@ -158,8 +368,25 @@ pub fn parse(
     (module, parser.errors)
 }
 
+/// `{1F600}` after a `\u`
+fn unicode_escape(chars: &mut std::str::Chars) -> Option<char> {
+    if chars.next() != Some('{') {
+        return None;
+    }
+    let mut code = String::new();
+    for c in chars.by_ref() {
+        if c == '}' {
+            return u32::from_str_radix(&code, 16).ok().and_then(char::from_u32);
+        }
+        code.push(c);
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::ast::{Expr, ExprIdx, Spanned};
+
     #[macro_export]
     #[cfg(test)]
     macro_rules! parse_success {
@@ -168,7 +395,7 @@ mod tests {
                     #[test]
                     fn [<test_parse_ $name>]() {
                         let mut expr_arena = la_arena::Arena::default();
-                        let (decls, errors) = crate::parse($src, &mut expr_arena, awlyc_error::FileId(smol_str::SmolStr::from("main")));
+                        let (decls, errors) = $crate::parse($src, &mut expr_arena, awlyc_error::FileId(smol_str::SmolStr::from("main")));
                         let s = format!("{:#?}\n{:#?}\n{:#?}", expr_arena, decls, errors);
                         insta::assert_snapshot!(s);
                     }
@@ -180,4 +407,530 @@ mod tests {
         basic_fn_decl,
         r#"fn host(foo, bar): "https://arewelangyet.com""#
     );
+
+    parse_success!(
+        schema_decl,
+        r#"schema Project { name: string, topics: [utils.Topic] }"#
+    );
+
+    parse_success!(
+        annotated_fn_decl,
+        r#"fn project(title: string, tags: [string]) -> { title: string, tags: [string] } { { title: title, tags: tags } }"#
+    );
+
+    parse_success!(
+        optional_types,
+        r#"schema Page { title: string, tags: [string]?, meta: { draft: bool? }? } fn parent(page: Page?) -> Page? { null }"#
+    );
+
+    parse_success!(postfix, r#"[utils.project("x").tags[0], a.b["c"], f()()]"#);
+
+    parse_success!(string_escapes, r#"{ "my key": "a\"b\\c\n\u{2713}\q" }"#);
+
+    #[test]
+    fn string_backslashes() {
+        let mut expr_arena = la_arena::Arena::default();
+        let (module, errors) = crate::parse(
+            r#"["C:\Users\awlyc", "\d+\.\w*", "\u0041"]"#,
+            &mut expr_arena,
+            awlyc_error::FileId(smol_str::SmolStr::from("main")),
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        let items = match &expr_arena[module.expr.unwrap()].inner {
+            Expr::Array(items) => items,
+            _ => unreachable!(),
+        };
+        let strings: Vec<_> = items
+            .iter()
+            .map(|item| match &expr_arena[*item].inner {
+                Expr::String(s) => s.as_str(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(strings, [r"C:\Users\awlyc", r"\d+\.\w*", r"\u0041"]);
+
+        assert_eq!(
+            parse_errors(r#""\u{zz}""#),
+            ["invalid escape sequence in string"]
+        );
+    }
+
+    /// Expression as an s-expression, `(+ a (* b c))`
+    fn sexp(expr_arena: &la_arena::Arena<Spanned<Expr>>, idx: ExprIdx) -> String {
+        let sexp = |idx| sexp(expr_arena, idx);
+        match &expr_arena[idx].inner {
+            Expr::Path(path) => path
+                .iter()
+                .map(|s| s.inner.as_str())
+                .collect::<Vec<_>>()
+                .join("."),
+            Expr::Int(n) => n.to_string(),
+            Expr::Binop(binop) => format!(
+                "({} {} {})",
+                binop.op.symbol(),
+                sexp(binop.lhs),
+                sexp(binop.rhs)
+            ),
+            Expr::Negate(negate) => format!("(- {})", sexp(negate.expr)),
+            Expr::Field(field) => format!("(. {} {})", sexp(field.expr), field.field.inner),
+            Expr::Index(index) => format!("([] {} {})", sexp(index.expr), sexp(index.index)),
+            Expr::Call(call) => {
+                let args: Vec<_> = call
+                    .args
+                    .iter()
+                    .map(|arg| match &arg.name {
+                        Some(name) => format!("{}: {}", name.inner, sexp(arg.value)),
+                        None if arg.spread => format!("...{}", sexp(arg.value)),
+                        None => sexp(arg.value),
+                    })
+                    .collect();
+                format!("(call {} [{}])", sexp(call.callee), args.join(" "))
+            }
+            expr => format!("{:?}", expr),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        const OPS: &[&str] = &["&", "+", "-", "*", "/", "%", "**"];
+        let mut srcs = vec![];
+        for op1 in OPS {
+            for op2 in OPS {
+                srcs.push(format!("a {} b {} c", op1, op2));
+            }
+        }
+        for op in OPS {
+            srcs.push(format!("-a {} b", op));
+            srcs.push(format!("a {} -b", op));
+        }
+        srcs.extend(
+            [
+                "--a",
+                "-a.b",
+                "-a[0] ** 2",
+                "a(1) + b()",
+                "f(x)(y).z[0] * 2",
+                "(a + b) * c",
+                "-(a + b)",
+                "(a & b).c",
+                "a * (b + c) ** 2",
+                "((a))",
+            ]
+            .map(String::from),
+        );
+
+        let mut out = String::new();
+        for src in srcs {
+            let mut expr_arena = la_arena::Arena::default();
+            let (module, errors) = crate::parse(
+                &src,
+                &mut expr_arena,
+                awlyc_error::FileId(smol_str::SmolStr::from("main")),
+            );
+            assert!(errors.is_empty(), "{}: {:#?}", src, errors);
+            out.push_str(&format!(
+                "{} => {}\n",
+                src,
+                sexp(&expr_arena, module.expr.unwrap())
+            ));
+        }
+        insta::assert_snapshot!(out);
+    }
+
+    /// Source text covered by the span of every expression, in the order they're allocated, which
+    /// is children before their parents
+    fn expr_spans(src: &str) -> Vec<&str> {
+        let mut expr_arena = la_arena::Arena::default();
+        let (_, errors) = crate::parse(
+            src,
+            &mut expr_arena,
+            awlyc_error::FileId(smol_str::SmolStr::from("main")),
+        );
+        assert!(errors.is_empty(), "{}: {:#?}", src, errors);
+        expr_arena
+            .iter()
+            .map(|(_, expr)| &src[expr.span.range])
+            .collect()
+    }
+
+    #[test]
+    fn fn_body_forms() {
+        let src = "fn a(x): x * 2 fn b(x) { x * 2 } fn c(x) -> int: x * 2
+fn d(): { k: 1 } fn e() { { k: 1 } } fn f() { k: 1 } fn g(k) { k, l: 2 } fn h() { \"k\": 1 }
+fn i(x) { ...x } fn j() { } f(1)";
+        let mut expr_arena = la_arena::Arena::default();
+        let (module, errors) = crate::parse(
+            src,
+            &mut expr_arena,
+            awlyc_error::FileId(smol_str::SmolStr::from("main")),
+        );
+        assert!(errors.is_empty(), "{:#?}", errors);
+        let bodies: Vec<_> = module.functions[..3]
+            .iter()
+            .map(|f| sexp(&expr_arena, f.body))
+            .collect();
+        assert_eq!(bodies, ["(* x 2)"; 3]);
+        let bodies: Vec<_> = module.functions[3..]
+            .iter()
+            .map(|f| match &expr_arena[f.body].inner {
+                Expr::Record(record) => record.entries.len(),
+                _ => panic!("{} isn't a record", &src[expr_arena[f.body].span.range]),
+            })
+            .collect();
+        assert_eq!(bodies, [1, 1, 1, 2, 1, 1, 0]);
+        assert_eq!(sexp(&expr_arena, module.expr.unwrap()), "(call f [1])");
+
+        // a colon body ends at a line break before `(` or `[`
+        for (src, body, expr) in [
+            ("fn f(x): x\n(1 + 2) * 3", "x", "(1 + 2) * 3"),
+            ("fn a(): 1\n[1, 2]", "1", "[1, 2]"),
+            ("fn a(): b.c\n(1)", "b.c", "(1)"),
+            ("fn f(x): g(x)[0]\n[x]", "g(x)[0]", "[x]"),
+            // inside brackets or braces the line break doesn't matter
+            ("fn f(x): [g\n(x)]\n(1)", "[g\n(x)]", "(1)"),
+            ("fn f(x) { x\n(1) }\n[f]", "x\n(1)", "[f]"),
+        ] {
+            let mut expr_arena = la_arena::Arena::default();
+            let (module, errors) = crate::parse(
+                src,
+                &mut expr_arena,
+                awlyc_error::FileId(smol_str::SmolStr::from("main")),
+            );
+            assert!(errors.is_empty(), "{:#?}", errors);
+            let text = |expr: ExprIdx| &src[expr_arena[expr].span.range];
+            assert_eq!(text(module.functions[0].body), body);
+            assert_eq!(text(module.expr.unwrap()), expr);
+        }
+    }
+
+    #[test]
+    fn named_args_and_defaults() {
+        let src = "fn f(a, b: int = 2, c = [1]) { a } [f(1, c: 3), f(b: 1, 2)]";
+        let mut expr_arena = la_arena::Arena::default();
+        let (module, errors) = crate::parse(
+            src,
+            &mut expr_arena,
+            awlyc_error::FileId(smol_str::SmolStr::from("main")),
+        );
+        let defaults: Vec<_> = module.functions[0]
+            .params
+            .0
+            .iter()
+            .map(|param| {
+                param
+                    .default
+                    .map(|default| &src[expr_arena[default].span.range])
+            })
+            .collect();
+        assert_eq!(defaults, [None, Some("2"), Some("[1]")]);
+        let calls: Vec<_> = match &expr_arena[module.expr.unwrap()].inner {
+            Expr::Array(els) => els.iter().map(|el| sexp(&expr_arena, *el)).collect(),
+            _ => unreachable!(),
+        };
+        assert_eq!(calls, ["(call f [1 c: 3])", "(call f [b: 1 2])"]);
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|err| (err.msg.as_str(), &src[err.span.range]))
+            .collect();
+        assert_eq!(
+            errors,
+            [("positional arguments can't follow named arguments", "2")]
+        );
+    }
+
+    #[test]
+    fn variadics_and_spreads() {
+        let src = "fn f(a, ...rest) { a } fn g(...xs = [], y) { y } [f(1, ...ys), f(...xs, b: 2)]";
+        let mut expr_arena = la_arena::Arena::default();
+        let (module, errors) = crate::parse(
+            src,
+            &mut expr_arena,
+            awlyc_error::FileId(smol_str::SmolStr::from("main")),
+        );
+        let variadic: Vec<_> = module.functions[0]
+            .params
+            .0
+            .iter()
+            .map(|param| param.variadic)
+            .collect();
+        assert_eq!(variadic, [false, true]);
+        let calls: Vec<_> = match &expr_arena[module.expr.unwrap()].inner {
+            Expr::Array(els) => els.iter().map(|el| sexp(&expr_arena, *el)).collect(),
+            _ => unreachable!(),
+        };
+        assert_eq!(calls, ["(call f [1 ...ys])", "(call f [...xs b: 2])"]);
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|err| (err.msg.as_str(), &src[err.span.range]))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("variadic parameters can't have a default value", "[]"),
+                ("only the last parameter can be variadic", "xs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans() {
+        assert_eq!(expr_spans("null  "), ["null"]);
+        assert_eq!(expr_spans("[1, 2.5] "), ["1", "2.5", "[1, 2.5]"]);
+        assert_eq!(
+            expr_spans(r#"{ a: "x", b, ...c }"#),
+            [r#""x""#, "b", "c", r#"{ a: "x", b, ...c }"#]
+        );
+        assert_eq!(
+            expr_spans("-a + b * 2"),
+            ["a", "-a", "b", "2", "b * 2", "-a + b * 2"]
+        );
+        assert_eq!(
+            expr_spans("(1 + 2) * 3"),
+            ["1", "2", "(1 + 2)", "3", "(1 + 2) * 3"]
+        );
+        assert_eq!(
+            expr_spans(r#"m.f(x, true).y[0] & a.b"#),
+            [
+                "m.f",
+                "x",
+                "true",
+                "m.f(x, true)",
+                "m.f(x, true).y",
+                "0",
+                "m.f(x, true).y[0]",
+                "a",
+                "a.b",
+                "m.f(x, true).y[0] & a.b",
+            ]
+        );
+        assert_eq!(expr_spans("fn f(x) { x }  f(1)"), ["x", "f", "1", "f(1)"]);
+    }
+
+    #[test]
+    fn decl_spans() {
+        let src = "fn f(a: [int], b: { c: string? } ) -> int { a }";
+        let mut expr_arena = la_arena::Arena::default();
+        let (module, errors) = crate::parse(
+            src,
+            &mut expr_arena,
+            awlyc_error::FileId(smol_str::SmolStr::from("main")),
+        );
+        assert!(errors.is_empty(), "{:#?}", errors);
+        let f = &module.functions[0];
+        let tys: Vec<_> = f
+            .params
+            .0
+            .iter()
+            .filter_map(|p| p.ty.as_ref())
+            .chain(&f.ret_ty)
+            .map(|ty| &src[ty.span.range])
+            .collect();
+        assert_eq!(tys, ["[int]", "{ c: string? }", "int"]);
+        assert_eq!(&src[f.params.span.range], "(a: [int], b: { c: string? } )");
+    }
+
+    #[test]
+    fn duplicates() {
+        let src = r#"import a "a.awlyc" import a "b.awlyc" schema S { x: int, x: int }
+fn f(x, y, x) { { k: 1, "k": 2, x, } } fn f() { 1 }
+{ name: "a", name: "b" }"#;
+        let mut expr_arena = la_arena::Arena::default();
+        let (module, errors) = crate::parse(
+            src,
+            &mut expr_arena,
+            awlyc_error::FileId(smol_str::SmolStr::from("main")),
+        );
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|err| {
+                let (first, label) = &err.labels[0];
+                assert!(first.range.start() < err.span.range.start());
+                let at = |range: text_size::TextRange| src[range].to_string();
+                (
+                    err.msg.as_str(),
+                    at(err.span.range),
+                    at(first.range),
+                    label.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "duplicate import `a`",
+                    "a".into(),
+                    "a".into(),
+                    "`a` first used here"
+                ),
+                (
+                    "duplicate field `x`",
+                    "x".into(),
+                    "x".into(),
+                    "`x` first used here"
+                ),
+                (
+                    "duplicate parameter `x`",
+                    "x".into(),
+                    "x".into(),
+                    "`x` first used here"
+                ),
+                (
+                    "duplicate key `k`",
+                    "\"k\"".into(),
+                    "k".into(),
+                    "`k` first used here"
+                ),
+                (
+                    "duplicate function `f`",
+                    "f".into(),
+                    "f".into(),
+                    "`f` first used here"
+                ),
+                (
+                    "duplicate key `name`",
+                    "name".into(),
+                    "name".into(),
+                    "`name` first used here"
+                ),
+            ]
+        );
+        // the first of duplicate keys is kept
+        let record = match &expr_arena[module.expr.unwrap()].inner {
+            crate::ast::Expr::Record(record) => record,
+            _ => unreachable!(),
+        };
+        assert_eq!(record.entries.len(), 1);
+        assert!(matches!(
+            expr_arena[record.field("name").unwrap()].inner,
+            crate::ast::Expr::String(ref s) if s == "a"
+        ));
+    }
+
+    fn parse_errors(src: &str) -> Vec<String> {
+        let mut expr_arena = la_arena::Arena::default();
+        let (_, errors) = crate::parse(
+            src,
+            &mut expr_arena,
+            awlyc_error::FileId(smol_str::SmolStr::from("main")),
+        );
+        errors.into_iter().map(|err| err.msg).collect()
+    }
+
+    #[test]
+    fn truncated_input() {
+        for src in [
+            "import",
+            "import a",
+            "schema",
+            "schema S {",
+            "fn",
+            "fn f(",
+            "fn f(x:",
+            "fn f() ->",
+            "{ a:",
+            "[1,",
+            "a.",
+            "a[",
+            "f(1,",
+            "-",
+            "1 +",
+            "\"abc",
+        ] {
+            assert!(
+                !parse_errors(src).is_empty(),
+                "{:?} parsed without errors",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let ok = format!("{}1{}", "[".repeat(60), "]".repeat(60));
+        assert_eq!(parse_errors(&ok), Vec::<String>::new());
+
+        for src in [
+            format!("{}1{}", "[".repeat(200), "]".repeat(200)),
+            format!("{}1{}", "(".repeat(200), ")".repeat(200)),
+            format!("{}1", "-".repeat(200)),
+            format!("{}1", "{ a: ".repeat(200)),
+            format!(
+                "fn f(x: {}int{}) {{ x }} 1",
+                "[".repeat(200),
+                "]".repeat(200)
+            ),
+        ] {
+            assert_eq!(
+                parse_errors(&src)[0],
+                "nested too deeply, at most 64 levels are allowed"
+            );
+        }
+    }
+
+    #[test]
+    fn expected_tokens() {
+        let cases = [
+            (
+                "[1, }",
+                "expected ']' or expression",
+                "while parsing this array",
+                "[1,",
+            ),
+            (
+                "{ a: 1 b: 2 }",
+                "expected '}' or ','",
+                "while parsing this record",
+                "{ a: 1",
+            ),
+            (
+                "f(1 2)",
+                "expected ')' or ','",
+                "while parsing this call",
+                "f(1",
+            ),
+            (
+                "fn f(x: ) { x }",
+                "expected type",
+                "while parsing this function",
+                "fn f(x:",
+            ),
+            (
+                "fn f(x y) { x }",
+                "expected ':', '=', ')' or ','",
+                "while parsing this function",
+                "fn f(x",
+            ),
+            (
+                "schema S [",
+                "expected '{'",
+                "while parsing this schema",
+                "schema S",
+            ),
+            (
+                "import 1",
+                "expected identifier",
+                "while parsing this import",
+                "import",
+            ),
+            (
+                "{ a: [int }",
+                "expected ']' or ','",
+                "while parsing this array",
+                "[int",
+            ),
+        ];
+        for (src, msg, label, labelled) in cases {
+            let mut expr_arena = la_arena::Arena::default();
+            let (_, errors) = crate::parse(
+                src,
+                &mut expr_arena,
+                awlyc_error::FileId(smol_str::SmolStr::from("main")),
+            );
+            let err = &errors[0];
+            assert_eq!(err.msg, msg, "{:?}", src);
+            assert_eq!(err.labels[0].1, label, "{:?}", src);
+            assert_eq!(&src[err.labels[0].0.range], labelled, "{:?}", src);
+        }
+    }
 }
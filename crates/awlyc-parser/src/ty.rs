@@ -0,0 +1,106 @@
+use super::*;
+
+use smol_str::SmolStr;
+
+use crate::ast::{Spanned, TypeExpr};
+
+const TYPE_RECOVERY_SET: &[TokenKind] = &[
+    TokenKind::Comma,
+    TokenKind::RParen,
+    TokenKind::RSquare,
+    TokenKind::RCurly,
+    TokenKind::LCurly,
+];
+const RECORD_TYPE_COLON_RECOVERY_SET: &[TokenKind] = &[
+    TokenKind::Ident,
+    TokenKind::Null,
+    TokenKind::LSquare,
+    TokenKind::LCurly,
+    TokenKind::RCurly,
+];
+const RECORD_TYPE_COMMA_RECOVERY_SET: &[TokenKind] = &[TokenKind::Ident, TokenKind::RCurly];
+
+impl<'src, I: Iterator<Item = Token> + Clone> Parser<'src, I> {
+    pub(crate) fn type_expr(&mut self) -> Spanned<TypeExpr> {
+        if let Err(span) = self.enter_nested() {
+            return Spanned {
+                inner: TypeExpr::Error,
+                span,
+            };
+        }
+        let ty = self.type_expr_inner();
+        self.exit_nested();
+        ty
+    }
+
+    fn type_expr_inner(&mut self) -> Spanned<TypeExpr> {
+        let start = self.peek_range().start();
+        let ty = if self.at(TokenKind::Null) {
+            TypeExpr::Named(vec![self.next().unwrap().text])
+        } else if self.at(TokenKind::Ident) {
+            let mut path = vec![self.next().unwrap().text];
+            while self.at(TokenKind::Period) {
+                self.next();
+                match self.expect(TokenKind::Ident, TYPE_RECOVERY_SET) {
+                    Some(segment) if segment.kind == TokenKind::Ident => path.push(segment.text),
+                    _ => break,
+                }
+            }
+            TypeExpr::Named(path)
+        } else if self.at(TokenKind::LSquare) {
+            self.enter_context("array type");
+            self.next();
+            let el = self.type_expr();
+            self.expect(TokenKind::RSquare, TYPE_RECOVERY_SET);
+            self.exit_context();
+            TypeExpr::Array(Box::new(el))
+        } else if self.at(TokenKind::LCurly) {
+            TypeExpr::Record(self.record_type())
+        } else {
+            self.error_expected();
+            TypeExpr::Error
+        };
+        let ty = Spanned {
+            inner: ty,
+            span: self.span_from(start),
+        };
+        if !self.at(TokenKind::Question) {
+            return ty;
+        }
+        self.next();
+        Spanned {
+            inner: TypeExpr::Optional(Box::new(ty)),
+            span: self.span_from(start),
+        }
+    }
+
+    /// `{ name: string, stars: int }`, also the body of a schema declaration
+    pub(crate) fn record_type(&mut self) -> Vec<(Spanned<SmolStr>, Spanned<TypeExpr>)> {
+        let mut fields = vec![];
+        let mut keys = HashMap::new();
+        self.enter_context("record type");
+        self.expect(TokenKind::LCurly, &[]); // checked before entering this function
+        while !self.at(TokenKind::RCurly) && !self.at_end() {
+            let key = match self.expect(TokenKind::Ident, TYPE_RECOVERY_SET) {
+                Some(key) if key.kind == TokenKind::Ident => key,
+                _ => break,
+            };
+            let key = Spanned {
+                inner: key.text,
+                span: Span {
+                    range: key.range,
+                    file_id: self.file_id.clone(),
+                },
+            };
+            self.check_duplicate(&mut keys, &key, "field");
+            self.expect(TokenKind::Colon, RECORD_TYPE_COLON_RECOVERY_SET);
+            fields.push((key, self.type_expr()));
+            if !self.at(TokenKind::RCurly) {
+                self.expect(TokenKind::Comma, RECORD_TYPE_COMMA_RECOVERY_SET);
+            }
+        }
+        self.expect(TokenKind::RCurly, TYPE_RECOVERY_SET);
+        self.exit_context();
+        fields
+    }
+}
@@ -0,0 +1,278 @@
+use std::{collections::HashMap, ops};
+
+use indexmap::IndexMap;
+use smol_str::SmolStr;
+
+use crate::AwlycValue;
+
+/// Something a value can be indexed with, a key for records and a position for arrays
+pub trait ValueIndex {
+    /// The part of `value` at this index, if `value` has one
+    fn index_into<'v>(&self, value: &'v AwlycValue) -> Option<&'v AwlycValue>;
+}
+
+impl ValueIndex for usize {
+    fn index_into<'v>(&self, value: &'v AwlycValue) -> Option<&'v AwlycValue> {
+        match value {
+            AwlycValue::Array(els) => els.get(*self),
+            _ => None,
+        }
+    }
+}
+
+impl ValueIndex for str {
+    fn index_into<'v>(&self, value: &'v AwlycValue) -> Option<&'v AwlycValue> {
+        match value {
+            AwlycValue::Record(fields) => fields.get(self),
+            _ => None,
+        }
+    }
+}
+
+impl ValueIndex for String {
+    fn index_into<'v>(&self, value: &'v AwlycValue) -> Option<&'v AwlycValue> {
+        self.as_str().index_into(value)
+    }
+}
+
+impl ValueIndex for SmolStr {
+    fn index_into<'v>(&self, value: &'v AwlycValue) -> Option<&'v AwlycValue> {
+        self.as_str().index_into(value)
+    }
+}
+
+impl<T> ValueIndex for &T
+where
+    T: ?Sized + ValueIndex,
+{
+    fn index_into<'v>(&self, value: &'v AwlycValue) -> Option<&'v AwlycValue> {
+        (**self).index_into(value)
+    }
+}
+
+static NULL: AwlycValue = AwlycValue::Null;
+
+/// `value["pages"][0]["title"]`, missing fields and elements are `null`
+impl<I> ops::Index<I> for AwlycValue
+where
+    I: ValueIndex,
+{
+    type Output = AwlycValue;
+
+    fn index(&self, index: I) -> &AwlycValue {
+        self.get(index).unwrap_or(&NULL)
+    }
+}
+
+impl AwlycValue {
+    /// Field of a record or element of an array
+    pub fn get<I: ValueIndex>(&self, index: I) -> Option<&AwlycValue> {
+        index.index_into(self)
+    }
+
+    /// Look up a nested value with a JSON pointer like `/pages/0/title`
+    /// `~1` and `~0` in a segment stand for `/` and `~`, the empty pointer is the value itself
+    pub fn pointer(&self, pointer: &str) -> Option<&AwlycValue> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        pointer
+            .strip_prefix('/')?
+            .split('/')
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+            .try_fold(self, |value, segment| match value {
+                AwlycValue::Array(_) => value.get(segment.parse::<usize>().ok()?),
+                _ => value.get(&segment),
+            })
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, AwlycValue::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            AwlycValue::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            AwlycValue::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Ints are converted, like where floats are expected in type annotations
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            AwlycValue::Int(v) => Some(*v as f64),
+            AwlycValue::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AwlycValue::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<AwlycValue>> {
+        match self {
+            AwlycValue::Array(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_record(&self) -> Option<&IndexMap<SmolStr, AwlycValue>> {
+        match self {
+            AwlycValue::Record(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($($variant:ident($($ty:ty),*)),* $(,)?) => {
+        $($(
+            impl From<$ty> for AwlycValue {
+                fn from(v: $ty) -> Self {
+                    AwlycValue::$variant(v.into())
+                }
+            }
+        )*)*
+    };
+}
+
+impl_from!(
+    Bool(bool),
+    Int(i8, i16, i32, i64, u8, u16, u32),
+    Float(f32, f64),
+    String(&str, String, SmolStr),
+);
+
+impl From<char> for AwlycValue {
+    fn from(v: char) -> Self {
+        AwlycValue::String(SmolStr::from(v.encode_utf8(&mut [0; 4])))
+    }
+}
+
+impl From<()> for AwlycValue {
+    fn from(_: ()) -> Self {
+        AwlycValue::Null
+    }
+}
+
+impl<T: Into<AwlycValue>> From<Option<T>> for AwlycValue {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(AwlycValue::Null, Into::into)
+    }
+}
+
+impl<T: Into<AwlycValue>> From<Vec<T>> for AwlycValue {
+    fn from(v: Vec<T>) -> Self {
+        v.into_iter().collect()
+    }
+}
+
+impl<T: Clone + Into<AwlycValue>> From<&[T]> for AwlycValue {
+    fn from(v: &[T]) -> Self {
+        v.iter().cloned().collect()
+    }
+}
+
+impl<K: Into<SmolStr>, V: Into<AwlycValue>> From<HashMap<K, V>> for AwlycValue {
+    fn from(v: HashMap<K, V>) -> Self {
+        v.into_iter().collect()
+    }
+}
+
+impl<K: Into<SmolStr>, V: Into<AwlycValue>> From<IndexMap<K, V>> for AwlycValue {
+    fn from(v: IndexMap<K, V>) -> Self {
+        v.into_iter().collect()
+    }
+}
+
+impl<T: Into<AwlycValue>> FromIterator<T> for AwlycValue {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        AwlycValue::Array(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<K: Into<SmolStr>, V: Into<AwlycValue>> FromIterator<(K, V)> for AwlycValue {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        AwlycValue::Record(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+/// Comparisons with Rust primitives, `value["stars"] == 3`
+macro_rules! impl_partial_eq {
+    ($($ty:ty => |$value:ident, $other:ident| $eq:expr,)*) => {
+        $(
+            impl PartialEq<$ty> for AwlycValue {
+                fn eq(&self, other: &$ty) -> bool {
+                    let ($value, $other) = (self, other);
+                    $eq
+                }
+            }
+
+            impl PartialEq<AwlycValue> for $ty {
+                fn eq(&self, other: &AwlycValue) -> bool {
+                    other == self
+                }
+            }
+        )*
+    };
+}
+
+impl_partial_eq! {
+    bool => |value, other| value.as_bool() == Some(*other),
+    i64 => |value, other| value.as_i64() == Some(*other),
+    f64 => |value, other| value.as_f64() == Some(*other),
+    str => |value, other| value.as_str() == Some(other),
+    &str => |value, other| value.as_str() == Some(*other),
+    String => |value, other| value.as_str() == Some(other.as_str()),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::lower_src, AwlycValue};
+
+    #[test]
+    fn accessors() {
+        let value = lower_src(
+            r#"{ pages: [{ title: "a", stars: 3 }, { title: "b", ratio: 0.5, "a/b": true }] }"#,
+        )
+        .unwrap()
+        .value;
+        assert_eq!(value["pages"][0]["title"], "a");
+        assert_eq!(value["pages"][0]["stars"].as_i64(), Some(3));
+        assert_eq!(value["pages"][1]["ratio"].as_f64(), Some(0.5));
+        assert!(value["pages"][5]["title"].is_null());
+        assert_eq!(
+            value.get("pages").and_then(|p| p.get(1)),
+            value.pointer("/pages/1")
+        );
+        assert_eq!(
+            value.pointer("/pages/1/title").and_then(|t| t.as_str()),
+            Some("b")
+        );
+        assert_eq!(
+            value.pointer("/pages/1/a~1b"),
+            Some(&AwlycValue::Bool(true))
+        );
+        assert_eq!(value.pointer(""), Some(&value));
+        assert_eq!(value.pointer("/pages/x"), None);
+        assert_eq!(value.pointer("pages"), None);
+        assert_eq!(value["pages"].as_array().map(Vec::len), Some(2));
+        assert_eq!(value.as_record().map(|r| r.len()), Some(1));
+        assert_eq!(value.as_str(), None);
+    }
+}
@@ -2,52 +2,545 @@
 // https://github.com/serde-rs/json/blob/dab5ed3ee97cef5e2b796513f8d9e4c7416e44bf/src/value/de.rs
 
 use core::slice;
-use std::collections::HashMap;
+use std::fmt;
 
-use awlyc_error::Diagnostic;
+use awlyc_error::{Diagnostic, DiagnosticKind, Span};
+use awlyc_hir::ty::{display_path, Segment};
+use indexmap::{map, IndexMap};
 use serde::{
     de::{
-        self, value::StringDeserializer, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess,
-        Unexpected,
+        self, value::BorrowedStrDeserializer, DeserializeOwned, DeserializeSeed, EnumAccess,
+        MapAccess, SeqAccess, Unexpected, VariantAccess,
     },
-    Deserializer,
+    Deserialize, Deserializer,
 };
 use smol_str::SmolStr;
 
-use crate::AwlycValue;
+use crate::{AwlycValue, SpannedValue, ValueSpans};
 
-pub fn from_awlyc_val<T>(value: &AwlycValue) -> Result<T, Diagnostic>
+/// Error produced when a value doesn't have the shape of the type it's deserialized into
+#[derive(Debug)]
+pub struct Error {
+    pub msg: String,
+    /// Path to the part of the value the error is about, e.g. `pages[2].title`
+    pub path: Vec<Segment>,
+    /// Where that part of the value comes from, if the value remembers it
+    pub span: Option<Span>,
+    /// Whether `path` and `span` have been set, errors are located at the innermost part they
+    /// concern and keep that location on their way out
+    located: bool,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.msg)
+        } else {
+            write!(f, "{} at `{}`", self.msg, display_path(&self.path))
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: fmt::Display,
+    {
+        Error {
+            msg: msg.to_string(),
+            path: vec![],
+            span: None,
+            located: false,
+        }
+    }
+}
+
+/// Values are deserialized from plain data, so any self-describing format can be converted into one
+impl<'de> Deserialize<'de> for AwlycValue {
+    fn deserialize<D>(deserializer: D) -> Result<AwlycValue, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> de::Visitor<'de> for ValueVisitor {
+    type Value = AwlycValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any awlyc value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<AwlycValue, E> {
+        Ok(AwlycValue::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<AwlycValue, E> {
+        Ok(AwlycValue::Int(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<AwlycValue, E>
+    where
+        E: de::Error,
+    {
+        match i64::try_from(v) {
+            Ok(v) => Ok(AwlycValue::Int(v)),
+            Err(_) => Err(E::invalid_value(
+                Unexpected::Unsigned(v),
+                &"an integer of at most 9223372036854775807",
+            )),
+        }
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<AwlycValue, E> {
+        Ok(AwlycValue::Float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<AwlycValue, E> {
+        Ok(AwlycValue::String(SmolStr::from(v)))
+    }
+
+    fn visit_none<E>(self) -> Result<AwlycValue, E> {
+        Ok(AwlycValue::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<AwlycValue, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_unit<E>(self) -> Result<AwlycValue, E> {
+        Ok(AwlycValue::Null)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<AwlycValue, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut els = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(el) = seq.next_element()? {
+            els.push(el);
+        }
+        Ok(AwlycValue::Array(els))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<AwlycValue, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut fields = IndexMap::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((key, value)) = map.next_entry::<String, AwlycValue>()? {
+            fields.insert(SmolStr::from(key), value);
+        }
+        Ok(AwlycValue::Record(fields))
+    }
+}
+
+/// Deserialize a value that doesn't remember where it comes from, errors only have a path
+pub fn from_awlyc_val<T>(value: &AwlycValue) -> Result<T, Error>
 where
     T: DeserializeOwned,
 {
     T::deserialize(value)
 }
 
-impl<'de, 'a: 'de> Deserializer<'de> for &'a AwlycValue {
-    type Error = Diagnostic;
+/// Deserialize a value produced by the evaluator, errors are reported where the offending part
+/// of the value comes from
+pub fn from_spanned_val<T>(value: &SpannedValue) -> Result<T, Diagnostic>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(ValueDeserializer::new(&value.value, Some(&value.spans))).map_err(|err| {
+        Diagnostic {
+            kind: DiagnosticKind::Error,
+            msg: err.to_string(),
+            // errors are always located, at the root of the value at least
+            span: err.span.unwrap_or_else(|| value.spans.span.clone()),
+            labels: vec![],
+        }
+    })
+}
+
+/// Deserializer for a part of a value, along with the spans of that part if they're known
+#[derive(Clone)]
+pub struct ValueDeserializer<'de> {
+    value: &'de AwlycValue,
+    spans: Option<&'de ValueSpans>,
+    path: Vec<Segment>,
+}
+
+impl<'de> ValueDeserializer<'de> {
+    pub fn new(value: &'de AwlycValue, spans: Option<&'de ValueSpans>) -> Self {
+        Self {
+            value,
+            spans,
+            path: vec![],
+        }
+    }
+
+    fn child(&self, value: &'de AwlycValue, segment: Segment) -> Self {
+        let spans = self.spans.and_then(|spans| match &segment {
+            Segment::Field(field) => spans.fields.get(field),
+            Segment::Index(idx) => spans.elements.get(*idx),
+        });
+        let mut path = self.path.clone();
+        path.push(segment);
+        Self { value, spans, path }
+    }
+
+    /// Attach the location of this part of the value to an error that doesn't have one yet
+    fn locate(&self, err: Error) -> Error {
+        if err.located {
+            return err;
+        }
+        Error {
+            path: self.path.clone(),
+            span: self.spans.map(|spans| spans.span.clone()),
+            located: true,
+            ..err
+        }
+    }
+
+    fn invalid_type(&self, exp: &dyn de::Expected) -> Error {
+        de::Error::invalid_type(self.value.unexpected(), exp)
+    }
+}
+
+/// Methods of `&AwlycValue` that only take a visitor, they deserialize the value without spans
+macro_rules! forward_to_value_deserializer {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: de::Visitor<'de>,
+            {
+                ValueDeserializer::new(self, None).$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for &'de AwlycValue {
+    type Error = Error;
+
+    forward_to_value_deserializer! {
+        deserialize_any deserialize_bool
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_option deserialize_unit
+        deserialize_seq deserialize_map deserialize_identifier deserialize_ignored_any
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        ValueDeserializer::new(self, None).deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        ValueDeserializer::new(self, None).deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        ValueDeserializer::new(self, None).deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        ValueDeserializer::new(self, None).deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        ValueDeserializer::new(self, None).deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        ValueDeserializer::new(self, None).deserialize_enum(name, variants, visitor)
+    }
+}
+
+/// Integers are range checked against the type they're deserialized into
+macro_rules! deserialize_int {
+    ($($method:ident => $visit:ident($ty:ty),)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: de::Visitor<'de>,
+            {
+                let result = match self.value {
+                    AwlycValue::Int(v) => match <$ty>::try_from(*v) {
+                        Ok(v) => visitor.$visit(v),
+                        Err(_) => Err(de::Error::custom(format_args!(
+                            "integer `{}` is out of range for `{}` ({} to {})",
+                            v,
+                            stringify!($ty),
+                            <$ty>::MIN,
+                            <$ty>::MAX,
+                        ))),
+                    },
+                    _ => Err(self.invalid_type(&visitor)),
+                };
+                result.map_err(|err| self.locate(err))
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        match &self {
+        let result = match self.value {
             AwlycValue::Null => visitor.visit_unit(),
+            AwlycValue::Bool(v) => visitor.visit_bool(*v),
             AwlycValue::Int(v) => visitor.visit_i64(*v),
             AwlycValue::Float(v) => visitor.visit_f64(*v),
-            AwlycValue::String(v) => visitor.visit_str(v),
-            AwlycValue::Record(v) => visit_object_ref(v, visitor),
-            AwlycValue::Array(v) => visit_array_ref(v, visitor),
-        }
+            AwlycValue::String(v) => visitor.visit_borrowed_str(v),
+            AwlycValue::Record(v) => visit_object_ref(&self, v, visitor),
+            AwlycValue::Array(v) => visit_array_ref(&self, v, visitor),
+        };
+        result.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let result = match self.value {
+            AwlycValue::Bool(v) => visitor.visit_bool(*v),
+            _ => Err(self.invalid_type(&visitor)),
+        };
+        result.map_err(|err| self.locate(err))
+    }
+
+    deserialize_int! {
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_i128 => visit_i128(i128),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+        deserialize_u128 => visit_u128(u128),
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_f64(visitor)
+    }
+
+    /// Ints are accepted where floats are expected, like in type annotations
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let result = match self.value {
+            AwlycValue::Int(v) => visitor.visit_f64(*v as f64),
+            AwlycValue::Float(v) => visitor.visit_f64(*v),
+            _ => Err(self.invalid_type(&visitor)),
+        };
+        result.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let result = match self.value {
+            AwlycValue::String(v) => {
+                let mut chars = v.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => visitor.visit_char(c),
+                    _ => Err(de::Error::invalid_value(
+                        Unexpected::Str(v),
+                        &"a single character",
+                    )),
+                }
+            }
+            _ => Err(self.invalid_type(&visitor)),
+        };
+        result.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let result = match self.value {
+            AwlycValue::String(v) => visitor.visit_borrowed_str(v),
+            _ => Err(self.invalid_type(&visitor)),
+        };
+        result.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    /// Bytes are either a string or an array of ints
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let result = match self.value {
+            AwlycValue::String(v) => visitor.visit_borrowed_bytes(v.as_bytes()),
+            AwlycValue::Array(v) => visit_array_ref(&self, v, visitor),
+            _ => Err(self.invalid_type(&visitor)),
+        };
+        result.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let result = match self.value {
+            AwlycValue::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self.clone()),
+        };
+        result.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let result = match self.value {
+            AwlycValue::Null => visitor.visit_unit(),
+            _ => Err(self.invalid_type(&visitor)),
+        };
+        result.map_err(|err| self.locate(err))
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    /// Newtype structs are deserialized from the value they wrap
+    fn deserialize_newtype_struct<V>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let this = self.clone();
+        visitor
+            .visit_newtype_struct(self)
+            .map_err(|err| this.locate(err))
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        match self {
-            AwlycValue::Array(v) => visit_array_ref(v, visitor),
+        let result = match self.value {
+            AwlycValue::Array(v) => visit_array_ref(&self, v, visitor),
             _ => Err(self.invalid_type(&visitor)),
-        }
+        };
+        result.map_err(|err| self.locate(err))
+    }
+
+    /// Tuples are deserialized from arrays, the visitor checks the length
+    fn deserialize_tuple<V>(self, _: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _: &'static str,
+        _: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let result = match self.value {
+            AwlycValue::Record(v) => visit_object_ref(&self, v, visitor),
+            _ => Err(self.invalid_type(&visitor)),
+        };
+        result.map_err(|err| self.locate(err))
     }
 
     fn deserialize_struct<V>(
@@ -59,43 +552,69 @@ impl<'de, 'a: 'de> Deserializer<'de> for &'a AwlycValue {
     where
         V: de::Visitor<'de>,
     {
-        match self {
-            AwlycValue::Array(v) => visit_array_ref(v, visitor),
-            AwlycValue::Record(v) => visit_object_ref(v, visitor),
+        let result = match self.value {
+            AwlycValue::Array(v) => visit_array_ref(&self, v, visitor),
+            AwlycValue::Record(v) => visit_object_ref(&self, v, visitor),
             _ => Err(self.invalid_type(&visitor)),
-        }
+        };
+        result.map_err(|err| self.locate(err))
     }
 
-    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    /// Externally tagged enums, unit variants are strings and other variants are records with a
+    /// single key, the variant's name
+    /// Internally and adjacently tagged enums go through `deserialize_any`
+    fn deserialize_enum<V>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        match self {
-            AwlycValue::Null => visitor.visit_none(),
-            _ => visitor.visit_some(self),
-        }
+        let result = match self.value {
+            AwlycValue::String(variant) => visitor.visit_enum(EnumDeserializer {
+                variant,
+                value: None,
+            }),
+            AwlycValue::Record(fields) if fields.len() == 1 => {
+                let (variant, value) = fields.iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer {
+                    variant,
+                    value: Some(self.child(value, Segment::Field(variant.clone()))),
+                })
+            }
+            AwlycValue::Record(_) => Err(de::Error::invalid_value(
+                Unexpected::Map,
+                &"a record with a single key",
+            )),
+            _ => Err(self.invalid_type(&visitor)),
+        };
+        result.map_err(|err| self.locate(err))
     }
 
-    serde::forward_to_deserialize_any! {
-        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char str string
-        bytes byte_buf unit map newtype_struct enum
-        ignored_any unit_struct tuple_struct tuple identifier
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
     }
-}
 
-impl AwlycValue {
-    fn invalid_type<E>(&self, exp: &dyn de::Expected) -> E
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
-        E: serde::de::Error,
+        V: de::Visitor<'de>,
     {
-        serde::de::Error::invalid_type(self.unexpected(), exp)
+        visitor.visit_unit()
     }
+}
 
-    fn unexpected(&self) -> Unexpected {
+impl AwlycValue {
+    fn unexpected(&self) -> Unexpected<'_> {
         match self {
             AwlycValue::Null => Unexpected::Unit,
-            AwlycValue::Int(_) => Unexpected::Signed(64),
-            AwlycValue::Float(_) => Unexpected::Float(64.0),
+            AwlycValue::Bool(v) => Unexpected::Bool(*v),
+            AwlycValue::Int(v) => Unexpected::Signed(*v),
+            AwlycValue::Float(v) => Unexpected::Float(*v),
             AwlycValue::String(v) => Unexpected::Str(v),
             AwlycValue::Array(_) => Unexpected::Seq,
             AwlycValue::Record(_) => Unexpected::Map,
@@ -103,16 +622,17 @@ impl AwlycValue {
     }
 }
 
-fn visit_array_ref<'de, V>(array: &'de [AwlycValue], visitor: V) -> Result<V::Value, Diagnostic>
+fn visit_array_ref<'de, V>(
+    parent: &ValueDeserializer<'de>,
+    array: &'de [AwlycValue],
+    visitor: V,
+) -> Result<V::Value, Error>
 where
     V: de::Visitor<'de>,
 {
     let len = array.len();
-    let mut deserializer = SeqRefDeserializer::new(array);
-    let seq = match visitor.visit_seq(&mut deserializer) {
-        core::result::Result::Ok(v) => v,
-        core::result::Result::Err(err) => return core::result::Result::Err(err),
-    };
+    let mut deserializer = SeqRefDeserializer::new(parent, array);
+    let seq = visitor.visit_seq(&mut deserializer)?;
     let remaining = deserializer.iter.len();
     if remaining == 0 {
         Ok(seq)
@@ -125,18 +645,16 @@ where
 }
 
 fn visit_object_ref<'de, V>(
-    object: &'de HashMap<SmolStr, AwlycValue>,
+    parent: &ValueDeserializer<'de>,
+    object: &'de IndexMap<SmolStr, AwlycValue>,
     visitor: V,
-) -> Result<V::Value, Diagnostic>
+) -> Result<V::Value, Error>
 where
     V: de::Visitor<'de>,
 {
     let len = object.len();
-    let mut deserializer = MapRefDeserializer::new(object);
-    let map = match visitor.visit_map(&mut deserializer) {
-        core::result::Result::Ok(v) => v,
-        core::result::Result::Err(err) => return core::result::Result::Err(err),
-    };
+    let mut deserializer = MapRefDeserializer::new(parent, object);
+    let map = visitor.visit_map(&mut deserializer)?;
     let remaining = deserializer.iter.len();
     if remaining == 0 {
         Ok(map)
@@ -148,25 +666,31 @@ where
     }
 }
 
-struct SeqRefDeserializer<'de> {
-    iter: slice::Iter<'de, AwlycValue>,
+struct SeqRefDeserializer<'p, 'de> {
+    parent: &'p ValueDeserializer<'de>,
+    iter: std::iter::Enumerate<slice::Iter<'de, AwlycValue>>,
 }
 
-impl<'de> SeqRefDeserializer<'de> {
-    fn new(slice: &'de [AwlycValue]) -> Self {
-        SeqRefDeserializer { iter: slice.iter() }
+impl<'p, 'de> SeqRefDeserializer<'p, 'de> {
+    fn new(parent: &'p ValueDeserializer<'de>, slice: &'de [AwlycValue]) -> Self {
+        SeqRefDeserializer {
+            parent,
+            iter: slice.iter().enumerate(),
+        }
     }
 }
 
-impl<'de> SeqAccess<'de> for SeqRefDeserializer<'de> {
-    type Error = Diagnostic;
+impl<'p, 'de> SeqAccess<'de> for SeqRefDeserializer<'p, 'de> {
+    type Error = Error;
 
-    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Diagnostic>
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
     where
         T: DeserializeSeed<'de>,
     {
         match self.iter.next() {
-            Some(value) => seed.deserialize(value).map(Some),
+            Some((idx, value)) => seed
+                .deserialize(self.parent.child(value, Segment::Index(idx)))
+                .map(Some),
             None => Ok(None),
         }
     }
@@ -179,43 +703,47 @@ impl<'de> SeqAccess<'de> for SeqRefDeserializer<'de> {
     }
 }
 
-struct MapRefDeserializer<'de> {
-    iter: <&'de HashMap<SmolStr, AwlycValue> as IntoIterator>::IntoIter,
-    value: Option<&'de AwlycValue>,
+struct MapRefDeserializer<'p, 'de> {
+    parent: &'p ValueDeserializer<'de>,
+    iter: map::Iter<'de, SmolStr, AwlycValue>,
+    value: Option<(&'de SmolStr, &'de AwlycValue)>,
 }
 
-impl<'de> MapRefDeserializer<'de> {
-    fn new(map: &'de HashMap<SmolStr, AwlycValue>) -> Self {
+impl<'p, 'de> MapRefDeserializer<'p, 'de> {
+    fn new(parent: &'p ValueDeserializer<'de>, map: &'de IndexMap<SmolStr, AwlycValue>) -> Self {
         MapRefDeserializer {
-            iter: map.into_iter(),
+            parent,
+            iter: map.iter(),
             value: None,
         }
     }
 }
 
-impl<'de> MapAccess<'de> for MapRefDeserializer<'de> {
-    type Error = Diagnostic;
+impl<'p, 'de> MapAccess<'de> for MapRefDeserializer<'p, 'de> {
+    type Error = Error;
 
-    fn next_key_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Diagnostic>
+    fn next_key_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
     where
         T: DeserializeSeed<'de>,
     {
         match self.iter.next() {
             Some((key, value)) => {
-                self.value = Some(value);
-                seed.deserialize(StringDeserializer::new(key.to_string()))
+                self.value = Some((key, value));
+                seed.deserialize(BorrowedStrDeserializer::new(key))
                     .map(Some)
             }
             None => Ok(None),
         }
     }
 
-    fn next_value_seed<T>(&mut self, seed: T) -> Result<T::Value, Diagnostic>
+    fn next_value_seed<T>(&mut self, seed: T) -> Result<T::Value, Error>
     where
         T: DeserializeSeed<'de>,
     {
         match self.value.take() {
-            Some(value) => seed.deserialize(value),
+            Some((key, value)) => {
+                seed.deserialize(self.parent.child(value, Segment::Field(key.clone())))
+            }
             None => Err(serde::de::Error::custom("value is missing")),
         }
     }
@@ -227,3 +755,244 @@ impl<'de> MapAccess<'de> for MapRefDeserializer<'de> {
         }
     }
 }
+
+/// An externally tagged enum, `value` is the variant's content, unit variants don't have one
+struct EnumDeserializer<'de> {
+    variant: &'de SmolStr,
+    value: Option<ValueDeserializer<'de>>,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer<'de> {
+    type Error = Error;
+    type Variant = VariantDeserializer<'de>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(BorrowedStrDeserializer::new(self.variant))?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer<'de> {
+    value: Option<ValueDeserializer<'de>>,
+}
+
+impl<'de> VariantAccess<'de> for VariantDeserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            Some(value) => de::Deserialize::deserialize(value),
+            None => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.value {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.value {
+            Some(value) => value.deserialize_tuple(len, visitor),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.value {
+            Some(value) => value.deserialize_struct("", fields, visitor),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"struct variant",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::{from_awlyc_val, from_spanned_val};
+    use crate::{tests::lower_src, AwlycValue};
+
+    #[test]
+    fn deserialization_errors_point_at_source() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Page {
+            title: String,
+        }
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Site {
+            pages: Vec<Page>,
+        }
+
+        let src =
+            r#"fn page(title) { { title: title } } { pages: [page("a"), page("b"), page(3)] }"#;
+        let value = lower_src(src).unwrap();
+        let err = from_spanned_val::<Site>(&value).unwrap_err();
+        assert_eq!(
+            err.msg,
+            "invalid type: integer `3`, expected a string at `pages[2].title`"
+        );
+        assert_eq!(&src[err.span.range], "3");
+    }
+
+    #[test]
+    fn deserialize_data_model() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum Shape {
+            Point,
+            Circle(f64),
+            Rect { w: u32, h: u32 },
+        }
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(tag = "kind")]
+        enum Internal {
+            Link { url: String },
+        }
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(tag = "t", content = "c")]
+        enum Adjacent {
+            Count(u8),
+        }
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Pair(i8, String);
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Port(u16);
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Marker;
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Config {
+            debug: bool,
+            shapes: Vec<Shape>,
+            internal: Internal,
+            adjacent: Adjacent,
+            pair: Pair,
+            port: Port,
+            marker: Marker,
+            env: HashMap<String, i64>,
+        }
+
+        let value = lower_src(
+            r#"{
+                debug: true,
+                shapes: ["Point", { Circle: 1 }, { Rect: { w: 2, h: 3 } }],
+                internal: { kind: "Link", url: "a" },
+                adjacent: { t: "Count", c: 4 },
+                pair: [-5, "b"],
+                port: 8080,
+                marker: null,
+                env: { a: 1, b: 2 },
+            }"#,
+        )
+        .unwrap();
+        let config = from_spanned_val::<Config>(&value).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                debug: true,
+                shapes: vec![Shape::Point, Shape::Circle(1.0), Shape::Rect { w: 2, h: 3 }],
+                internal: Internal::Link {
+                    url: "a".to_string()
+                },
+                adjacent: Adjacent::Count(4),
+                pair: Pair(-5, "b".to_string()),
+                port: Port(8080),
+                marker: Marker,
+                env: HashMap::from([("a".to_string(), 1), ("b".to_string(), 2)]),
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_errors() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        enum Shape {
+            Point,
+            Circle(f64),
+        }
+
+        let err = |src| {
+            let value = lower_src(src).unwrap();
+            (
+                from_spanned_val::<(u8, Vec<Shape>)>(&value)
+                    .unwrap_err()
+                    .msg,
+                from_spanned_val::<bool>(&value).unwrap_err().msg,
+            )
+        };
+        assert_eq!(
+            err(r#"[300, []]"#).0,
+            "integer `300` is out of range for `u8` (0 to 255) at `[0]`"
+        );
+        assert_eq!(
+            err(r#"[-1, []]"#).1,
+            "invalid type: sequence, expected a boolean"
+        );
+        assert_eq!(
+            err(r#"[1, ["Square"]]"#).0,
+            "unknown variant `Square`, expected `Point` or `Circle` at `[1][0]`"
+        );
+        assert_eq!(
+            err(r#"[1, [{ Circle: "a" }]]"#).0,
+            "invalid type: string \"a\", expected f64 at `[1][0].Circle`"
+        );
+        assert_eq!(
+            err(r#"[1, [{ Point: null, Circle: 1 }]]"#).0,
+            "invalid value: map, expected a record with a single key at `[1][0]`"
+        );
+        assert_eq!(
+            err(r#"[1]"#).0,
+            "invalid length 1, expected a tuple of size 2"
+        );
+    }
+
+    #[test]
+    fn value_deserialize() {
+        let value: AwlycValue = serde_json::from_str(r#"{"a":[1,2.5,"s",null,true]}"#).unwrap();
+        assert_eq!(value.to_string(), r#"{ a: [1, 2.5, "s", null, true] }"#);
+
+        // through awlyc's own deserializer
+        let value = lower_src(r#"[1, 2.5, "a", null, true, { a: [] }]"#)
+            .unwrap()
+            .value;
+        let copy: AwlycValue = from_awlyc_val(&value).unwrap();
+        assert_eq!(copy.to_string(), value.to_string());
+        assert_eq!(
+            serde_json::from_str::<AwlycValue>("18446744073709551615")
+                .unwrap_err()
+                .to_string(),
+            "invalid value: integer `18446744073709551615`, expected an integer of at most 9223372036854775807 at line 1 column 20"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt::{self, Write};
+
+use awlyc_lexer::{lex, TokenKind};
+
+use crate::AwlycValue;
+
+const INDENT: &str = "  ";
+
+/// Values are displayed as awlyc source
+/// `{}` writes everything on one line, `{:#}` spreads arrays and records over multiple lines
+impl fmt::Display for AwlycValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write_pretty(self, f, 0)
+        } else {
+            write_compact(self, f)
+        }
+    }
+}
+
+fn write_scalar(value: &AwlycValue, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match value {
+        AwlycValue::Null => write!(f, "null"),
+        AwlycValue::Bool(v) => write!(f, "{}", v),
+        AwlycValue::String(v) => write_string(v, f),
+        AwlycValue::Int(v) => write!(f, "{}", v),
+        // float literals always have a fractional part and never an exponent
+        AwlycValue::Float(v) if v.fract() == 0.0 => write!(f, "{:.1}", v),
+        AwlycValue::Float(v) => write!(f, "{}", v),
+        AwlycValue::Array(_) | AwlycValue::Record(_) => unreachable!(),
+    }
+}
+
+fn write_compact(value: &AwlycValue, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match value {
+        AwlycValue::Array(els) => {
+            f.write_char('[')?;
+            for (i, el) in els.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_compact(el, f)?;
+            }
+            f.write_char(']')
+        }
+        AwlycValue::Record(fields) if fields.is_empty() => f.write_str("{}"),
+        AwlycValue::Record(fields) => {
+            f.write_str("{ ")?;
+            for (i, (k, v)) in fields.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_key(k, f)?;
+                f.write_str(": ")?;
+                write_compact(v, f)?;
+            }
+            f.write_str(" }")
+        }
+        _ => write_scalar(value, f),
+    }
+}
+
+fn write_pretty(value: &AwlycValue, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+    match value {
+        AwlycValue::Array(els) if els.is_empty() => f.write_str("[]"),
+        AwlycValue::Array(els) => {
+            f.write_str("[\n")?;
+            for el in els {
+                f.write_str(&INDENT.repeat(depth + 1))?;
+                write_pretty(el, f, depth + 1)?;
+                f.write_str(",\n")?;
+            }
+            write!(f, "{}]", INDENT.repeat(depth))
+        }
+        AwlycValue::Record(fields) if fields.is_empty() => f.write_str("{}"),
+        AwlycValue::Record(fields) => {
+            f.write_str("{\n")?;
+            for (k, v) in fields {
+                f.write_str(&INDENT.repeat(depth + 1))?;
+                write_key(k, f)?;
+                f.write_str(": ")?;
+                write_pretty(v, f, depth + 1)?;
+                f.write_str(",\n")?;
+            }
+            write!(f, "{}}}", INDENT.repeat(depth))
+        }
+        _ => write_scalar(value, f),
+    }
+}
+
+/// Keys that aren't identifiers, including keywords, are quoted
+fn write_key(key: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut tokens = lex(key);
+    match (tokens.next(), tokens.next()) {
+        (Some(tok), None) if tok.kind == TokenKind::Ident && tok.text == key => f.write_str(key),
+        _ => write_string(key, f),
+    }
+}
+
+fn write_string(s: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\0' => f.write_str("\\0")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
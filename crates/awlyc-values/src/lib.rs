@@ -1,214 +1,625 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
 
 use awlyc_error::{Diagnostic, DiagnosticKind, FileId, Span};
+use awlyc_hir::{
+    bind_args,
+    ty::{invalid_index, no_field, not_spreadable, Mismatch, MismatchKind, Segment, Ty},
+    Argument, FnId, Resolution, Resolutions,
+};
 use awlyc_parser::{
-    ast::{Binop, BinopKind, Expr, ExprIdx, FnDecl, Spanned},
+    ast::{Arg, Binop, BinopKind, Call, Expr, ExprIdx, FnDecl, RecordEntry, Spanned, TypeExpr},
     Module,
 };
 
-use itertools::Itertools;
+use indexmap::IndexMap;
 use la_arena::Arena;
-use serde::Deserialize;
 use smol_str::SmolStr;
 use text_size::TextRange;
 
-type ValueResult = Result<AwlycValue, Diagnostic>;
+/// `None` is the poison value, it's produced by expressions that failed to evaluate
+/// The error has already been recorded at that point, operations on poison values just produce
+/// poison themselves so that a single mistake doesn't cause a cascade of errors
+type ValueResult = Option<SpannedValue>;
+
+/// Lowering stops recording diagnostics after this many, a note says how many more there were
+pub const MAX_DIAGNOSTICS: usize = 50;
+
+/// Calls can't be nested deeper than this by default, there are no conditionals so a recursive
+/// function never returns anyway
+pub const MAX_CALL_DEPTH: usize = 64;
+
+/// Strings built by repetition can't be longer than this, in bytes
+const MAX_REPEATED_LEN: usize = 1 << 24;
 
+mod access;
 pub mod deserialize;
+mod display;
+#[macro_use]
+mod macros;
+pub mod serialize;
+mod spanned;
 
-#[derive(Debug, Deserialize)]
+pub use access::ValueIndex;
+pub use spanned::{SpannedValue, ValueSpans};
+
+/// Limits on the work lowering does before giving up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Calls can't be nested deeper than this
+    /// Every call takes some native stack, very deep limits need a thread with a larger stack
+    pub max_call_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_call_depth: MAX_CALL_DEPTH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum AwlycValue {
     Null,
+    Bool(bool),
     String(SmolStr),
     Int(i64),
     Float(f64),
     Array(Vec<AwlycValue>),
-    Record(HashMap<SmolStr, AwlycValue>),
+    /// Fields are kept in the order they're written in, equality doesn't depend on it
+    Record(IndexMap<SmolStr, AwlycValue>),
+}
+
+impl AwlycValue {
+    /// Type of the value, arrays with elements of different types are `[any]`
+    pub fn ty(&self) -> Ty {
+        match self {
+            AwlycValue::Null => Ty::Null,
+            AwlycValue::Bool(_) => Ty::Bool,
+            AwlycValue::String(_) => Ty::String,
+            AwlycValue::Int(_) => Ty::Int,
+            AwlycValue::Float(_) => Ty::Float,
+            AwlycValue::Array(els) => {
+                let mut tys = els.iter().map(AwlycValue::ty);
+                let el = match tys.next() {
+                    Some(first) if tys.all(|ty| ty == first) => first,
+                    _ => Ty::Unknown,
+                };
+                Ty::Array(Box::new(el))
+            }
+            AwlycValue::Record(fields) => {
+                Ty::Record(fields.iter().map(|(k, v)| (k.clone(), v.ty())).collect())
+            }
+        }
+    }
+
+    /// Every part of the value that doesn't match `ty`
+    pub fn mismatches(&self, ty: &Ty) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+        self.collect_mismatches(ty, &mut vec![], &mut mismatches);
+        mismatches
+    }
+
+    fn collect_mismatches(&self, ty: &Ty, path: &mut Vec<Segment>, out: &mut Vec<Mismatch>) {
+        match (self, ty) {
+            (AwlycValue::Null, Ty::Optional(_)) => {}
+            (value @ (AwlycValue::Array(_) | AwlycValue::Record(_)), Ty::Optional(ty)) => {
+                value.collect_mismatches(ty, path, out)
+            }
+            (AwlycValue::Array(els), Ty::Array(el)) => {
+                for (idx, value) in els.iter().enumerate() {
+                    path.push(Segment::Index(idx));
+                    value.collect_mismatches(el, path, out);
+                    path.pop();
+                }
+            }
+            (AwlycValue::Record(fields), ty) if ty.is_record() => {
+                out.extend(ty.field_mismatches(path, fields.keys()));
+                for (k, ty) in ty.fields().unwrap() {
+                    if let Some(value) = fields.get(&k) {
+                        path.push(Segment::Field(k));
+                        value.collect_mismatches(&ty, path, out);
+                        path.pop();
+                    }
+                }
+            }
+            (value, ty) => {
+                let found = value.ty();
+                if !ty.accepts(&found) {
+                    out.push(Mismatch {
+                        path: path.clone(),
+                        kind: MismatchKind::Type {
+                            expected: ty.clone(),
+                            found,
+                        },
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// A call being lowered
+struct Frame {
+    /// The call expression
+    call: ExprIdx,
+    /// Name of the function called
+    name: SmolStr,
+    /// Values passed by the call's arguments, spread arrays are passed element by element
+    args: Vec<AwlycValue>,
 }
 
 struct LoweringCtx<'a> {
-    entry: &'a PathBuf,
     modules: &'a HashMap<FileId, Module>,
     expr_arena: &'a Arena<Spanned<Expr>>,
+    resolutions: &'a Resolutions,
+    limits: Limits,
+    errors: RefCell<Vec<Diagnostic>>,
+    /// Errors past `MAX_DIAGNOSTICS` that weren't recorded, and where the first of them is
+    dropped: RefCell<Option<(usize, Span)>>,
+    /// Calls being lowered, one inside the other, the innermost last
+    calls: RefCell<Vec<Frame>>,
+    /// Set once runaway recursion is found, every call after that fails without being lowered
+    /// so a function calling itself more than once doesn't take exponential time to give up
+    gave_up: Cell<bool>,
 }
 
 impl<'a> LoweringCtx<'a> {
     pub fn new(
-        entry: &'a PathBuf,
         modules: &'a HashMap<FileId, Module>,
         expr_arena: &'a Arena<Spanned<Expr>>,
+        resolutions: &'a Resolutions,
+        limits: Limits,
     ) -> Self {
         Self {
-            entry,
             modules,
             expr_arena,
+            resolutions,
+            limits,
+            errors: RefCell::new(vec![]),
+            dropped: RefCell::new(None),
+            calls: RefCell::new(vec![]),
+            gave_up: Cell::new(false),
         }
     }
 
-    fn file_id(&self, path: &PathBuf) -> FileId {
-        FileId(SmolStr::from(path.to_str().unwrap()))
+    /// Record an error, the returned poison value should be used as the result of the failed expression
+    /// The calls being lowered are added as labels, a function called from many places can fail
+    /// because of what only one of them passed
+    fn error<T>(&self, mut diagnostic: Diagnostic) -> Option<T> {
+        let mut errors = self.errors.borrow_mut();
+        if errors.len() < MAX_DIAGNOSTICS {
+            diagnostic.labels.extend(self.trace());
+            errors.push(diagnostic);
+        } else {
+            self.dropped
+                .borrow_mut()
+                .get_or_insert((0, diagnostic.span))
+                .0 += 1;
+        }
+        None
+    }
+
+    /// Labels for the calls being lowered, outermost first
+    /// Each call site is labeled once, with how many of the calls were made there
+    fn trace(&self) -> Vec<(Span, String)> {
+        let calls = self.calls.borrow();
+        let mut sites: IndexMap<ExprIdx, (&SmolStr, usize)> = IndexMap::new();
+        for frame in calls.iter() {
+            sites.entry(frame.call).or_insert((&frame.name, 0)).1 += 1;
+        }
+        sites
+            .into_iter()
+            .map(|(call, (name, count))| {
+                let msg = match count {
+                    1 => format!("`{}` called here", name),
+                    _ => format!("`{}` called here {} times", name, count),
+                };
+                (self.expr_arena[call].span.clone(), msg)
+            })
+            .collect()
     }
 
-    pub(crate) fn lower(&self) -> ValueResult {
-        let module = &self.modules[&self.file_id(self.entry)];
+    fn finish(self, value: ValueResult) -> Result<SpannedValue, Vec<Diagnostic>> {
+        let mut errors = self.errors.into_inner();
+        if let Some((count, span)) = self.dropped.into_inner() {
+            errors.push(Diagnostic {
+                kind: DiagnosticKind::Note,
+                msg: format!("{} more errors not shown", count),
+                span,
+                labels: vec![],
+            });
+        }
+        match value {
+            Some(value) if errors.is_empty() => Ok(value),
+            _ => Err(errors),
+        }
+    }
+
+    pub(crate) fn lower(&self, module_id: &FileId) -> ValueResult {
+        let module = &self.modules[module_id];
         if let Some(expr) = module.expr {
-            self.lower_expr(expr, module, None)
+            self.lower_expr(expr, &[])
         } else {
-            return Err(Diagnostic {
+            self.error(Diagnostic {
                 kind: DiagnosticKind::Error,
-                msg: format!("missing expression (nothing to evaluate)"),
+                msg: "missing expression (nothing to evaluate)".to_string(),
                 span: Span {
                     range: TextRange::new(0.into(), 0.into()),
-                    file_id: self.file_id(self.entry),
+                    file_id: module_id.clone(),
                 },
-            });
+                labels: vec![],
+            })
         }
     }
 
-    /// Index of the expression to lower, optionally pass substitutions
-    /// Substitutions are basically the function parameters
-    /// When a function is called, uses of the parameters are just substitutions of the arguments
-    fn lower_expr(
-        &self,
-        idx: ExprIdx,
-        module: &Module,
-        substitutions: Option<&[(SmolStr, ExprIdx)]>,
-    ) -> ValueResult {
+    /// Lower the expression at `idx`
+    /// `params` are the arguments of the function call the expression is part of, paths resolved to
+    /// a parameter are substituted with the argument in that position
+    fn lower_expr(&self, idx: ExprIdx, params: &[SpannedValue]) -> ValueResult {
+        let span = self.expr_arena[idx].span.clone();
         let val = match &self.expr_arena[idx].inner {
-            // This is gonna be a substitution
-            Expr::Path(path) => {
-                assert_eq!(path.len(), 1);
-                let path = path.first().unwrap();
-                if let Some(substitutions) = substitutions {
-                    for (name, expr) in substitutions {
-                        if *name == path.inner {
-                            return self.lower_expr(*expr, module, Some(substitutions));
-                        }
-                    }
-                    return Err(Diagnostic {
-                        kind: DiagnosticKind::Error,
-                        msg: format!("unknown identifier referenced `{}`", path.inner),
-                        span: path.span.clone(),
-                    });
-                } else {
-                    return Err(Diagnostic {
-                        kind: DiagnosticKind::Error,
-                        msg: format!("unknown identifier referenced `{}`", path.inner),
-                        span: path.span.clone(),
-                    });
-                }
-            }
+            // the argument keeps the spans of where it was produced
+            Expr::Path(_) => match self.resolutions.get(idx) {
+                Some(Resolution::Param(param)) => return Some(params[*param].clone()),
+                _ => unreachable!(), // we dont lower if there are resolution errors
+            },
             Expr::Null => AwlycValue::Null,
-            Expr::Int(n) => AwlycValue::Int((*n).try_into().unwrap()),
-            Expr::Binop(binop) => self.lower_binop_expr(binop, module, substitutions)?,
+            Expr::Bool(v) => AwlycValue::Bool(*v),
+            Expr::Int(n) => match i64::try_from(*n) {
+                Ok(n) => AwlycValue::Int(n),
+                Err(_) => return self.error(int_too_large(*n, span)),
+            },
+            Expr::Binop(binop) if matches!(binop.op, BinopKind::Merge) => {
+                return self.lower_merge(binop, span, params)
+            }
+            Expr::Binop(binop) => self.lower_binop_expr(binop, params)?,
             Expr::Float(n) => AwlycValue::Float(*n),
             Expr::String(v) => AwlycValue::String(v.clone()),
+            // `-9223372036854775808` is in range but its literal isn't
+            Expr::Negate(n) if self.is_min_int_literal(n.expr) => AwlycValue::Int(i64::MIN),
             Expr::Negate(n) => {
-                let expr = self.lower_expr(n.expr, module, substitutions)?;
-                match expr {
-                    AwlycValue::Int(n) => AwlycValue::Int(n * -1),
-                    AwlycValue::Float(n) => AwlycValue::Float(n * -1.0),
+                let expr = self.lower_expr(n.expr, params)?;
+                match expr.value {
+                    AwlycValue::Int(n) => match n.checked_neg() {
+                        Some(n) => AwlycValue::Int(n),
+                        None => {
+                            return self.error(Diagnostic {
+                                kind: DiagnosticKind::Error,
+                                msg: format!("integer overflow in `-({})`", n),
+                                span,
+                                labels: vec![],
+                            })
+                        }
+                    },
+                    AwlycValue::Float(n) => AwlycValue::Float(-n),
                     _ => {
-                        return Err(Diagnostic {
+                        return self.error(Diagnostic {
                             kind: DiagnosticKind::Error,
-                            msg: format!("invalid negation operation"),
-                            span: self.expr_arena[idx].span.clone(),
+                            msg: "invalid negation operation".to_string(),
+                            span,
+                            labels: vec![],
                         })
                     }
                 }
             }
+            // every element is lowered, even after one fails, so all of their errors are reported
             Expr::Array(els) => {
-                let mut arr = vec![];
-                for el in els {
-                    arr.push(self.lower_expr(*el, module, substitutions)?);
-                }
-                AwlycValue::Array(arr)
+                let arr: Vec<_> = els.iter().map(|el| self.lower_expr(*el, params)).collect();
+                return Some(SpannedValue::array(
+                    arr.into_iter().collect::<Option<_>>()?,
+                    span,
+                ));
             }
-            Expr::Record(fields) => {
-                let mut record = HashMap::new();
-                for (k, v) in fields.0.iter() {
-                    record.insert(k.clone(), self.lower_expr(*v, module, substitutions)?);
-                }
-                AwlycValue::Record(record)
+            Expr::Record(record) => {
+                let entries: Vec<_> = record
+                    .entries
+                    .iter()
+                    .map(|entry| match entry {
+                        RecordEntry::Field(k, v) => {
+                            Some(vec![(k.clone(), self.lower_expr(*v, params)?)])
+                        }
+                        RecordEntry::Spread(spread) => self.lower_spread(*spread, params),
+                    })
+                    .collect();
+                let entries = entries.into_iter().collect::<Option<Vec<_>>>()?;
+                // later fields override earlier ones with the same key
+                let fields: IndexMap<_, _> = entries.into_iter().flatten().collect();
+                return Some(SpannedValue::record(fields.into_iter().collect(), span));
             }
             Expr::Call(call) => {
-                let callee = match &self.expr_arena[call.callee].inner {
-                    Expr::Path(path) => path,
+                let fn_id = match self.resolutions.get(call.callee) {
+                    Some(Resolution::Function(fn_id)) => fn_id,
                     _ => unreachable!(),
                 };
-
-                // only allow foo() or foo.bar()
-                if callee.len() > 2 {
-                    return Err(Diagnostic {
-                        kind: DiagnosticKind::Error,
-                        msg: format!(
-                            "unknown function referenced `{}`",
-                            callee.iter().map(|s| s.to_string()).join(".")
-                        ),
-                        span: self.expr_arena[call.callee].span.clone(),
-                    });
+                let f = &self.modules[&fn_id.module].functions[fn_id.idx];
+                // arguments are lowered where the call is, the callee only sees their values
+                let passed: Vec<_> = call
+                    .args
+                    .iter()
+                    .map(|arg| self.lower_arg(arg, params))
+                    .collect();
+                let passed = passed.into_iter().collect::<Option<Vec<_>>>()?;
+                if self.gave_up.get() {
+                    return None;
                 }
-
-                // There's no import
-                if callee.len() == 1 {
-                    let f_name = callee.first().unwrap();
-                    let f = self.find_function_in_module(f_name, module)?;
-                    return self.expand_function(f, &call.args, module);
+                let frame = Frame {
+                    call: idx,
+                    name: f.name.inner.clone(),
+                    args: passed
+                        .iter()
+                        .flatten()
+                        .map(|arg| arg.value.clone())
+                        .collect(),
+                };
+                if let Some(error) = self.runaway_recursion(&frame) {
+                    self.gave_up.set(true);
+                    return self.error(error);
                 }
+                let bound = self.bind_args(f, call, &passed)?;
+                self.calls.borrow_mut().push(frame);
+                let value = self.lower_call(fn_id, call, &bound, &passed);
+                self.calls.borrow_mut().pop();
+                return value;
+            }
+            Expr::Field(field) => {
+                let value = self.lower_expr(field.expr, params)?;
+                return self.lower_field(value, &field.field, field.field.span.clone());
+            }
+            Expr::Index(index) => {
+                let value = self.lower_expr(index.expr, params);
+                let idx_value = self.lower_expr(index.index, params);
+                let (value, idx_value) = (value?, idx_value?);
+                let idx_span = self.expr_arena[index.index].span.clone();
+                return match (&value.value, &idx_value.value) {
+                    (AwlycValue::Array(els), AwlycValue::Int(i)) => {
+                        let len = els.len();
+                        match usize::try_from(*i).ok().and_then(|i| value.into_element(i)) {
+                            Some(el) => Some(el),
+                            None => self.error(Diagnostic {
+                                kind: DiagnosticKind::Error,
+                                msg: format!(
+                                    "index out of bounds: the length is {} but the index is {}",
+                                    len, i
+                                ),
+                                span: idx_span,
+                                labels: vec![],
+                            }),
+                        }
+                    }
+                    (AwlycValue::Record(_), AwlycValue::String(key)) => {
+                        let key = key.clone();
+                        self.lower_field(value, &key, idx_span)
+                    }
+                    (value, idx_value) => {
+                        self.error(invalid_index(&value.ty(), &idx_value.ty(), span))
+                    }
+                };
+            }
+            Expr::Error => unreachable!(), // we dont lower if there are Expr::Errors
+        };
+        Some(SpannedValue::new(val, span))
+    }
 
-                let import_alias = callee.first().unwrap(); // import foo "path.awlyc" -- foo is the import_alias
-                if let Some(import) = module
-                    .imports
-                    .iter()
-                    .find(|import| import.name == *import_alias.inner)
-                {
-                    let mut path = self.entry.clone();
-                    path.pop();
-                    path.push(import.path.as_str());
-                    let module_id = self.file_id(&path);
-                    let module = &self.modules[&module_id];
-                    let f = self.find_function_in_module(callee.last().unwrap(), module)?;
-                    return self.expand_function(f, &call.args, module);
-                } else {
-                    return Err(Diagnostic {
-                        kind: DiagnosticKind::Error,
-                        msg: format!("unknown module referenced `{}`", import_alias.inner),
-                        span: import_alias.span.clone(),
-                    });
+    /// Error for making the call `frame` inside the calls being lowered, if it recurses forever or
+    /// nests calls too deeply
+    /// Like any other error, it gets the chain of calls that led to it as labels
+    fn runaway_recursion(&self, frame: &Frame) -> Option<Diagnostic> {
+        let calls = self.calls.borrow();
+        // there are no conditionals, a call made again with the same arguments repeats everything
+        // it did the first time, including calling itself again
+        let msg = if calls
+            .iter()
+            .any(|outer| outer.call == frame.call && outer.args == frame.args)
+        {
+            format!(
+                "infinite recursion: `{}` is called again with the same arguments",
+                frame.name
+            )
+        } else if calls.len() >= self.limits.max_call_depth {
+            format!(
+                "calls nested too deeply calling `{}`, at most {} are allowed",
+                frame.name, self.limits.max_call_depth
+            )
+        } else {
+            return None;
+        };
+        Some(Diagnostic {
+            kind: DiagnosticKind::Error,
+            msg,
+            span: self.expr_arena[frame.call].span.clone(),
+            labels: vec![],
+        })
+    }
+
+    /// Values an argument passes, the elements of an array spread into the call or just its value
+    fn lower_arg(&self, arg: &Arg, params: &[SpannedValue]) -> Option<Vec<SpannedValue>> {
+        let value = self.lower_expr(arg.value, params)?;
+        if !arg.spread {
+            return Some(vec![value]);
+        }
+        let ty = value.value.ty();
+        match value.into_elements() {
+            Some(els) => Some(els),
+            None => self.error(not_spreadable(&ty, self.expr_arena[arg.value].span.clone())),
+        }
+    }
+
+    /// Parameters of `f` bound to the values `passed` by each argument of `call`
+    /// Only the lengths of spread arrays can make the arguments not fit, everything else has been
+    /// checked before evaluation
+    fn bind_args(
+        &self,
+        f: &FnDecl,
+        call: &Call,
+        passed: &[Vec<SpannedValue>],
+    ) -> Option<Vec<Argument>> {
+        let lens: Vec<_> = passed.iter().map(Vec::len).collect();
+        match bind_args(f, call, self.expr_arena, Some(&lens)) {
+            Ok(bound) => Some(bound),
+            Err(errors) => {
+                for error in errors {
+                    self.error::<()>(error);
                 }
+                None
             }
-            _ => unreachable!(), // we dont lower if there are Expr::Errors
-        };
-        Ok(val)
+        }
     }
 
-    fn lower_binop_expr(
+    /// Result of calling `fn_id` with its parameters `bound` to the values `passed` in `call`
+    /// The result keeps the spans of the expressions in the callee that produced it
+    fn lower_call(
         &self,
-        binop: &Binop,
-        module: &Module,
-        substitutions: Option<&[(SmolStr, ExprIdx)]>,
+        fn_id: &FnId,
+        call: &Call,
+        bound: &[Argument],
+        passed: &[Vec<SpannedValue>],
     ) -> ValueResult {
+        let f = &self.modules[&fn_id.module].functions[fn_id.idx];
+        // default values are lowered as part of the call, one that calls its own function is cut
+        // off like any other recursion
+        let args: Vec<_> = bound
+            .iter()
+            .map(|arg| self.bound_arg(arg, passed, &call.args.span))
+            .collect();
+        let args = args.into_iter().collect::<Option<Vec<_>>>()?;
+        let checked: Vec<_> = args
+            .iter()
+            .zip(&f.params.0)
+            .filter_map(|(value, param)| {
+                let ty = param.ty.as_ref()?;
+                Some(self.expect(fn_id, &ty.inner, value))
+            })
+            .collect();
+        checked.into_iter().collect::<Option<Vec<_>>>()?;
+        let value = self.lower_expr(f.body, &args)?;
+        if let Some(ty) = &f.ret_ty {
+            self.expect(fn_id, &ty.inner, &value)?;
+        }
+        Some(value)
+    }
+
+    /// Value of a parameter bound to `arg`, an array collecting the rest of the arguments gets
+    /// `span`
+    fn bound_arg(&self, arg: &Argument, passed: &[Vec<SpannedValue>], span: &Span) -> ValueResult {
+        match arg {
+            Argument::Passed(idx) => Some(passed[*idx][0].clone()),
+            Argument::Element(idx, el) => Some(passed[*idx][*el].clone()),
+            Argument::Default(default) => self.lower_expr(*default, &[]),
+            Argument::Rest(args) => {
+                let els: Vec<_> = args
+                    .iter()
+                    .map(|arg| self.bound_arg(arg, passed, span))
+                    .collect();
+                Some(SpannedValue::array(
+                    els.into_iter().collect::<Option<_>>()?,
+                    span.clone(),
+                ))
+            }
+            Argument::Unknown => unreachable!(), // the lengths of spread arrays are known
+        }
+    }
+
+    /// Check a value against an annotation of function `fn_id`, every mismatch is reported where
+    /// the mismatched part of the value comes from, values that don't match are replaced by poison
+    fn expect(&self, fn_id: &FnId, ty: &TypeExpr, value: &SpannedValue) -> Option<()> {
+        let ty = Ty::from_annotation(ty, &fn_id.module, self.modules);
+        let mismatches = value.value.mismatches(&ty);
+        for mismatch in &mismatches {
+            self.error::<()>(Diagnostic {
+                kind: DiagnosticKind::Error,
+                msg: mismatch.to_string(),
+                span: value.spans.at(&mismatch.path).span.clone(),
+                labels: vec![],
+            });
+        }
+        mismatches.is_empty().then_some(())
+    }
+
+    /// Field `field` of `value`, `span` is where the field is named
+    fn lower_field(&self, value: SpannedValue, field: &str, span: Span) -> ValueResult {
+        let ty = value.value.ty();
+        let found = value
+            .into_fields()
+            .and_then(|fields| fields.into_iter().find(|(k, _)| k == field));
+        match found {
+            Some((_, value)) => Some(value),
+            None => self.error(no_field(field, &ty, span)),
+        }
+    }
+
+    /// Fields of the record spread with `...spread`
+    fn lower_spread(
+        &self,
+        spread: ExprIdx,
+        params: &[SpannedValue],
+    ) -> Option<Vec<(SmolStr, SpannedValue)>> {
+        let value = self.lower_expr(spread, params)?;
+        let ty = value.value.ty();
+        value.into_fields().or_else(|| {
+            self.error(Diagnostic {
+                kind: DiagnosticKind::Error,
+                msg: format!("only records can be spread, found `{}`", ty),
+                span: self.expr_arena[spread].span.clone(),
+                labels: vec![],
+            })
+        })
+    }
+
+    /// Merges keep the spans of the fields of both operands
+    fn lower_merge(&self, binop: &Binop, span: Span, params: &[SpannedValue]) -> ValueResult {
+        let lhs = self.lower_expr(binop.lhs, params);
+        let rhs = self.lower_expr(binop.rhs, params);
+        let (lhs, rhs) = (lhs?, rhs?);
+        match (&lhs.value, &rhs.value) {
+            (AwlycValue::Record(_), AwlycValue::Record(_)) => Some(lhs.merge(rhs, span)),
+            _ => self.error(Diagnostic {
+                kind: DiagnosticKind::Error,
+                msg: format!(
+                    "invalid merge operands: `{}` & `{}`",
+                    lhs.value.ty(),
+                    rhs.value.ty()
+                ),
+                span: Span::combine(
+                    &self.expr_arena[binop.lhs].span,
+                    &self.expr_arena[binop.rhs].span,
+                ),
+                labels: vec![],
+            }),
+        }
+    }
+
+    fn is_min_int_literal(&self, idx: ExprIdx) -> bool {
+        matches!(self.expr_arena[idx].inner, Expr::Int(n) if n == i64::MIN.unsigned_abs())
+    }
+
+    fn lower_binop_expr(&self, binop: &Binop, params: &[SpannedValue]) -> Option<AwlycValue> {
         // TODO: code duplication...
         match binop.op {
-            BinopKind::Add => self.lower_binop_add(binop, module, substitutions),
-            BinopKind::Sub => self.lower_binop_sub(binop, module, substitutions),
-            BinopKind::Mul => self.lower_binop_mul(binop, module, substitutions),
-            BinopKind::Div => self.lower_binop_div(binop, module, substitutions),
+            BinopKind::Add => self.lower_binop_add(binop, params),
+            BinopKind::Sub => self.lower_binop_sub(binop, params),
+            BinopKind::Mul => self.lower_binop_mul(binop, params),
+            BinopKind::Div => self.lower_binop_div(binop, params),
+            BinopKind::Rem => self.lower_binop_rem(binop, params),
+            BinopKind::Pow => self.lower_binop_pow(binop, params),
+            BinopKind::Merge => unreachable!(), // see lower_merge
         }
     }
 
-    fn lower_binop_add(
-        &self,
-        binop: &Binop,
-        module: &Module,
-        substitutions: Option<&[(SmolStr, ExprIdx)]>,
-    ) -> ValueResult {
+    fn lower_binop_add(&self, binop: &Binop, params: &[SpannedValue]) -> Option<AwlycValue> {
         let lhs_span = &self.expr_arena[binop.lhs].span.clone();
         let rhs_span = &self.expr_arena[binop.rhs].span.clone();
-        let lhs = self.lower_expr(binop.lhs, module, substitutions)?;
-        let rhs = self.lower_expr(binop.rhs, module, substitutions)?;
+        let lhs = self.lower_expr(binop.lhs, params);
+        let rhs = self.lower_expr(binop.rhs, params);
+        let (lhs, rhs) = (lhs?.value, rhs?.value);
         let result = match (lhs, rhs) {
-            (AwlycValue::Int(a), AwlycValue::Int(b)) => AwlycValue::Int(a + b),
+            (AwlycValue::Int(a), AwlycValue::Int(b)) => match a.checked_add(b) {
+                Some(n) => AwlycValue::Int(n),
+                None => return self.error(overflow(binop, a, b, lhs_span, rhs_span)),
+            },
             (AwlycValue::Float(a), AwlycValue::Float(b)) => AwlycValue::Float(a + b),
             (AwlycValue::Int(a), AwlycValue::Float(b)) => AwlycValue::Float(a as f64 + b),
             (AwlycValue::Float(a), AwlycValue::Int(b)) => AwlycValue::Float(a + b as f64),
@@ -216,153 +627,728 @@ impl<'a> LoweringCtx<'a> {
                 AwlycValue::String(SmolStr::from(format!("{}{}", a, b)))
             }
             _ => {
-                return Err(Diagnostic {
+                return self.error(Diagnostic {
                     kind: DiagnosticKind::Error,
-                    msg: format!("invalid addition operands"),
-                    span: Span::combine(&lhs_span, &rhs_span),
+                    msg: "invalid addition operands".to_string(),
+                    span: Span::combine(lhs_span, rhs_span),
+                    labels: vec![],
                 })
             }
         };
-        Ok(result)
+        Some(result)
     }
 
-    fn lower_binop_sub(
-        &self,
-        binop: &Binop,
-        module: &Module,
-        substitutions: Option<&[(SmolStr, ExprIdx)]>,
-    ) -> ValueResult {
+    fn lower_binop_sub(&self, binop: &Binop, params: &[SpannedValue]) -> Option<AwlycValue> {
         // TODO: should string subtraction be allowed? probably not but ask
 
         let lhs_span = &self.expr_arena[binop.lhs].span.clone();
         let rhs_span = &self.expr_arena[binop.rhs].span.clone();
-        let lhs = self.lower_expr(binop.lhs, module, substitutions)?;
-        let rhs = self.lower_expr(binop.rhs, module, substitutions)?;
+        let lhs = self.lower_expr(binop.lhs, params);
+        let rhs = self.lower_expr(binop.rhs, params);
+        let (lhs, rhs) = (lhs?.value, rhs?.value);
         let result = match (lhs, rhs) {
-            (AwlycValue::Int(a), AwlycValue::Int(b)) => AwlycValue::Int(a - b),
+            (AwlycValue::Int(a), AwlycValue::Int(b)) => match a.checked_sub(b) {
+                Some(n) => AwlycValue::Int(n),
+                None => return self.error(overflow(binop, a, b, lhs_span, rhs_span)),
+            },
             (AwlycValue::Float(a), AwlycValue::Float(b)) => AwlycValue::Float(a - b),
             (AwlycValue::Int(a), AwlycValue::Float(b)) => AwlycValue::Float(a as f64 - b),
             (AwlycValue::Float(a), AwlycValue::Int(b)) => AwlycValue::Float(a - b as f64),
             _ => {
-                return Err(Diagnostic {
+                return self.error(Diagnostic {
                     kind: DiagnosticKind::Error,
-                    msg: format!("invalid subtraction operands"),
-                    span: Span::combine(&lhs_span, &rhs_span),
+                    msg: "invalid subtraction operands".to_string(),
+                    span: Span::combine(lhs_span, rhs_span),
+                    labels: vec![],
                 })
             }
         };
-        Ok(result)
+        Some(result)
     }
 
-    fn lower_binop_mul(
-        &self,
-        binop: &Binop,
-        module: &Module,
-        substitutions: Option<&[(SmolStr, ExprIdx)]>,
-    ) -> ValueResult {
+    fn lower_binop_mul(&self, binop: &Binop, params: &[SpannedValue]) -> Option<AwlycValue> {
         // TODO: can we do a cursed string * float multiplication
         // ex: "hello" * 1.5 = "hellohel"
         let lhs_span = &self.expr_arena[binop.lhs].span.clone();
         let rhs_span = &self.expr_arena[binop.rhs].span.clone();
-        let lhs = self.lower_expr(binop.lhs, module, substitutions)?;
-        let rhs = self.lower_expr(binop.rhs, module, substitutions)?;
+        let lhs = self.lower_expr(binop.lhs, params);
+        let rhs = self.lower_expr(binop.rhs, params);
+        let (lhs, rhs) = (lhs?.value, rhs?.value);
         let result = match (lhs, rhs) {
-            (AwlycValue::Int(a), AwlycValue::Int(b)) => AwlycValue::Int(a * b),
+            (AwlycValue::Int(a), AwlycValue::Int(b)) => match a.checked_mul(b) {
+                Some(n) => AwlycValue::Int(n),
+                None => return self.error(overflow(binop, a, b, lhs_span, rhs_span)),
+            },
             (AwlycValue::Float(a), AwlycValue::Float(b)) => AwlycValue::Float(a * b),
             (AwlycValue::Int(a), AwlycValue::Float(b)) => AwlycValue::Float(a as f64 * b),
             (AwlycValue::Float(a), AwlycValue::Int(b)) => AwlycValue::Float(a * b as f64),
             (AwlycValue::String(a), AwlycValue::Int(n)) => {
-                AwlycValue::String(SmolStr::from(a.repeat(n as usize)))
+                let len = usize::try_from(n)
+                    .ok()
+                    .and_then(|n| a.len().checked_mul(n))
+                    .filter(|len| *len <= MAX_REPEATED_LEN);
+                match len {
+                    Some(_) => AwlycValue::String(SmolStr::from(a.repeat(n as usize))),
+                    None if n < 0 => {
+                        return self.error(Diagnostic {
+                            kind: DiagnosticKind::Error,
+                            msg: format!(
+                                "cannot repeat a string a negative number of times: {}",
+                                n
+                            ),
+                            span: rhs_span.clone(),
+                            labels: vec![],
+                        })
+                    }
+                    None => {
+                        return self.error(Diagnostic {
+                            kind: DiagnosticKind::Error,
+                            msg: format!(
+                                "repeated string is too long, at most {} bytes are allowed",
+                                MAX_REPEATED_LEN
+                            ),
+                            span: Span::combine(lhs_span, rhs_span),
+                            labels: vec![],
+                        })
+                    }
+                }
             }
             _ => {
-                // TODO: since we have multiple call sites each with potentially different operand types, this err msg is not very useful. Add label with span of callsite so user knows which one is wrong
-                return Err(Diagnostic {
+                return self.error(Diagnostic {
                     kind: DiagnosticKind::Error,
-                    msg: format!("invalid multiplication operands"),
-                    span: Span::combine(&lhs_span, &rhs_span),
+                    msg: "invalid multiplication operands".to_string(),
+                    span: Span::combine(lhs_span, rhs_span),
+                    labels: vec![],
                 });
             }
         };
-        Ok(result)
+        Some(result)
     }
 
-    fn lower_binop_div(
-        &self,
-        binop: &Binop,
-        module: &Module,
-        substitutions: Option<&[(SmolStr, ExprIdx)]>,
-    ) -> ValueResult {
+    fn lower_binop_div(&self, binop: &Binop, params: &[SpannedValue]) -> Option<AwlycValue> {
         let lhs_span = &self.expr_arena[binop.lhs].span.clone();
         let rhs_span = &self.expr_arena[binop.rhs].span.clone();
-        let lhs = self.lower_expr(binop.lhs, module, substitutions)?;
-        let rhs = self.lower_expr(binop.rhs, module, substitutions)?;
+        let lhs = self.lower_expr(binop.lhs, params);
+        let rhs = self.lower_expr(binop.rhs, params);
+        let (lhs, rhs) = (lhs?.value, rhs?.value);
         let result = match (lhs, rhs) {
-            (AwlycValue::Int(a), AwlycValue::Int(b)) => AwlycValue::Int(a / b),
+            (AwlycValue::Int(_), AwlycValue::Int(0)) => {
+                return self.error(Diagnostic {
+                    kind: DiagnosticKind::Error,
+                    msg: "division by zero".to_string(),
+                    span: rhs_span.clone(),
+                    labels: vec![],
+                })
+            }
+            (AwlycValue::Int(a), AwlycValue::Int(b)) => match a.checked_div(b) {
+                Some(n) => AwlycValue::Int(n),
+                None => return self.error(overflow(binop, a, b, lhs_span, rhs_span)),
+            },
             (AwlycValue::Float(a), AwlycValue::Float(b)) => AwlycValue::Float(a / b),
             (AwlycValue::Int(a), AwlycValue::Float(b)) => AwlycValue::Float(a as f64 / b),
             (AwlycValue::Float(a), AwlycValue::Int(b)) => AwlycValue::Float(a / b as f64),
             _ => {
-                return Err(Diagnostic {
+                return self.error(Diagnostic {
                     kind: DiagnosticKind::Error,
-                    msg: format!("invalid division operands"),
-                    span: Span::combine(&lhs_span, &rhs_span),
+                    msg: "invalid division operands".to_string(),
+                    span: Span::combine(lhs_span, rhs_span),
+                    labels: vec![],
                 })
             }
         };
-        Ok(result)
+        Some(result)
     }
 
-    fn find_function_in_module(
-        &self,
-        name: &Spanned<SmolStr>,
-        module: &'a Module,
-    ) -> Result<&'a FnDecl, Diagnostic> {
-        if let Some(function) = module.functions.iter().find(|f| f.name.inner == name.inner) {
-            return Ok(&function);
-        } else {
-            return Err(Diagnostic {
-                kind: DiagnosticKind::Error,
-                msg: format!("unknown function referenced `{}`", name.inner),
-                span: name.span.clone(),
-            });
-        }
+    fn lower_binop_rem(&self, binop: &Binop, params: &[SpannedValue]) -> Option<AwlycValue> {
+        let lhs_span = &self.expr_arena[binop.lhs].span.clone();
+        let rhs_span = &self.expr_arena[binop.rhs].span.clone();
+        let lhs = self.lower_expr(binop.lhs, params);
+        let rhs = self.lower_expr(binop.rhs, params);
+        let (lhs, rhs) = (lhs?.value, rhs?.value);
+        let result = match (lhs, rhs) {
+            (AwlycValue::Int(_), AwlycValue::Int(0)) => {
+                return self.error(Diagnostic {
+                    kind: DiagnosticKind::Error,
+                    msg: "remainder with a divisor of zero".to_string(),
+                    span: rhs_span.clone(),
+                    labels: vec![],
+                })
+            }
+            // only `i64::MIN % -1` overflows, its remainder is 0
+            (AwlycValue::Int(a), AwlycValue::Int(b)) => AwlycValue::Int(a.wrapping_rem(b)),
+            (AwlycValue::Float(a), AwlycValue::Float(b)) => AwlycValue::Float(a % b),
+            (AwlycValue::Int(a), AwlycValue::Float(b)) => AwlycValue::Float(a as f64 % b),
+            (AwlycValue::Float(a), AwlycValue::Int(b)) => AwlycValue::Float(a % b as f64),
+            _ => {
+                return self.error(Diagnostic {
+                    kind: DiagnosticKind::Error,
+                    msg: "invalid remainder operands".to_string(),
+                    span: Span::combine(lhs_span, rhs_span),
+                    labels: vec![],
+                })
+            }
+        };
+        Some(result)
     }
 
-    fn expand_function(
-        &self,
-        f: &FnDecl,
-        args: &Spanned<Vec<ExprIdx>>,
-        module: &Module,
-    ) -> ValueResult {
-        let params_len = f.params.0.len();
-        let args_len = args.len();
-        if params_len != args_len {
-            return Err(Diagnostic {
-                kind: DiagnosticKind::Error,
-                msg: format!(
-                    "incorrect number of arguments supplied to `{}`",
-                    f.name.inner
-                ),
-                span: args.span.clone(),
-            });
-        }
-        let substitutions: &[(SmolStr, ExprIdx)] = &f
-            .params
-            .0
-            .iter()
-            .zip(&args.inner)
-            .map(|(param, arg)| (param.0.clone(), *arg))
-            .collect::<Vec<_>>();
-        self.lower_expr(f.body, module, Some(substitutions))
+    fn lower_binop_pow(&self, binop: &Binop, params: &[SpannedValue]) -> Option<AwlycValue> {
+        let lhs_span = &self.expr_arena[binop.lhs].span.clone();
+        let rhs_span = &self.expr_arena[binop.rhs].span.clone();
+        let lhs = self.lower_expr(binop.lhs, params);
+        let rhs = self.lower_expr(binop.rhs, params);
+        let (lhs, rhs) = (lhs?.value, rhs?.value);
+        let result = match (lhs, rhs) {
+            (AwlycValue::Int(_), AwlycValue::Int(b)) if b < 0 => {
+                return self.error(Diagnostic {
+                    kind: DiagnosticKind::Error,
+                    msg: format!("negative exponent `{}` in an integer power", b),
+                    span: Span::combine(lhs_span, rhs_span),
+                    labels: vec![],
+                })
+            }
+            (AwlycValue::Int(a), AwlycValue::Int(b)) => {
+                match u32::try_from(b).ok().and_then(|exp| a.checked_pow(exp)) {
+                    Some(n) => AwlycValue::Int(n),
+                    None => return self.error(overflow(binop, a, b, lhs_span, rhs_span)),
+                }
+            }
+            (AwlycValue::Float(a), AwlycValue::Float(b)) => AwlycValue::Float(a.powf(b)),
+            (AwlycValue::Int(a), AwlycValue::Float(b)) => AwlycValue::Float((a as f64).powf(b)),
+            (AwlycValue::Float(a), AwlycValue::Int(b)) => AwlycValue::Float(a.powf(b as f64)),
+            _ => {
+                return self.error(Diagnostic {
+                    kind: DiagnosticKind::Error,
+                    msg: "invalid exponentiation operands".to_string(),
+                    span: Span::combine(lhs_span, rhs_span),
+                    labels: vec![],
+                })
+            }
+        };
+        Some(result)
+    }
+}
+
+/// Error for an integer that doesn't fit in 64 bits
+fn int_too_large(n: u64, span: Span) -> Diagnostic {
+    Diagnostic {
+        kind: DiagnosticKind::Error,
+        msg: format!(
+            "integer `{}` is out of range, integers are between {} and {}",
+            n,
+            i64::MIN,
+            i64::MAX
+        ),
+        span,
+        labels: vec![],
+    }
+}
+
+/// Error for an operation on integers whose result doesn't fit in 64 bits
+fn overflow(binop: &Binop, a: i64, b: i64, lhs_span: &Span, rhs_span: &Span) -> Diagnostic {
+    Diagnostic {
+        kind: DiagnosticKind::Error,
+        msg: format!("integer overflow in `{} {} {}`", a, binop.op.symbol(), b),
+        span: Span::combine(lhs_span, rhs_span),
+        labels: vec![],
     }
 }
 
+/// Lower the expression of the entry module, the value remembers where each of its parts comes from
+/// `resolutions` must come from resolving `modules` without errors
 pub fn lower(
-    entry: &str,
+    entry: &FileId,
     modules: &HashMap<FileId, Module>,
     expr_arena: &Arena<Spanned<Expr>>,
-) -> Result<AwlycValue, Diagnostic> {
-    let entry = std::fs::canonicalize(entry).unwrap();
-    let ctx = LoweringCtx::new(&entry, modules, expr_arena);
-    ctx.lower()
+    resolutions: &Resolutions,
+    limits: Limits,
+) -> Result<SpannedValue, Vec<Diagnostic>> {
+    let ctx = LoweringCtx::new(modules, expr_arena, resolutions, limits);
+    let value = ctx.lower(entry);
+    ctx.finish(value)
+}
+
+/// Lower a single expression that has been resolved outside of a function
+pub fn lower_expr(
+    expr: ExprIdx,
+    modules: &HashMap<FileId, Module>,
+    expr_arena: &Arena<Spanned<Expr>>,
+    resolutions: &Resolutions,
+    limits: Limits,
+) -> Result<SpannedValue, Vec<Diagnostic>> {
+    let ctx = LoweringCtx::new(modules, expr_arena, resolutions, limits);
+    let value = ctx.lower_expr(expr, &[]);
+    ctx.finish(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use awlyc_error::{Diagnostic, DiagnosticKind, FileId};
+    use awlyc_hir::resolve;
+    use awlyc_parser::parse;
+    use la_arena::Arena;
+    use smol_str::SmolStr;
+
+    use crate::{lower_expr, AwlycValue, Limits, SpannedValue, MAX_DIAGNOSTICS};
+
+    pub(crate) fn lower_src(src: &str) -> Result<SpannedValue, Vec<Diagnostic>> {
+        lower_src_with(src, Limits::default())
+    }
+
+    pub(crate) fn lower_src_with(
+        src: &str,
+        limits: Limits,
+    ) -> Result<SpannedValue, Vec<Diagnostic>> {
+        let file_id = FileId(SmolStr::from("main"));
+        let mut expr_arena = Arena::default();
+        let (module, errors) = parse(src, &mut expr_arena, file_id.clone());
+        assert!(errors.is_empty(), "{:#?}", errors);
+        let expr = module.expr.unwrap();
+        let modules = HashMap::from([(file_id, module)]);
+        let (resolutions, errors) = resolve(&modules, &expr_arena);
+        assert!(errors.is_empty(), "{:#?}", errors);
+        lower_expr(expr, &modules, &expr_arena, &resolutions, limits)
+    }
+
+    pub(crate) fn messages(errors: &[Diagnostic]) -> Vec<&str> {
+        errors.iter().map(|e| e.msg.as_str()).collect()
+    }
+
+    /// Each error's message with the source text it points at
+    pub(crate) fn located<'a>(errors: &'a [Diagnostic], src: &'a str) -> Vec<(&'a str, &'a str)> {
+        errors
+            .iter()
+            .map(|e| (e.msg.as_str(), &src[e.span.range]))
+            .collect()
+    }
+
+    /// The source text of each of the error's labels with the label's message
+    pub(crate) fn labels<'a>(error: &'a Diagnostic, src: &'a str) -> Vec<(&'a str, &'a str)> {
+        error
+            .labels
+            .iter()
+            .map(|(span, msg)| (&src[span.range], msg.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn lowering_reports_every_error() {
+        let errors =
+            lower_src(r#"fn f(x) { x * {} } [1 - "a", { a: f(2) }, -[], f(-"b")]"#).unwrap_err();
+        let msgs = messages(&errors);
+        assert_eq!(
+            msgs,
+            [
+                "invalid subtraction operands",
+                "invalid multiplication operands",
+                "invalid negation operation",
+                "invalid negation operation",
+            ]
+        );
+    }
+
+    #[test]
+    fn too_many_errors() {
+        let src = format!("[{}]", vec![r#"1 - "a""#; MAX_DIAGNOSTICS + 3].join(", "));
+        let errors = lower_src(&src).unwrap_err();
+        assert_eq!(errors.len(), MAX_DIAGNOSTICS + 1);
+        let note = errors.last().unwrap();
+        assert_eq!(note.kind, DiagnosticKind::Note);
+        assert_eq!(note.msg, "3 more errors not shown");
+    }
+
+    #[test]
+    fn poison_does_not_cascade() {
+        let errors = lower_src(r#"-("a" + 1)"#).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "invalid addition operands");
+    }
+
+    #[test]
+    fn operators() {
+        let value = lower_src("[1 + 2 * 3, (1 + 2) * 3, -2 ** 2, 2 ** 3 ** 2, 7 % 3, -7 % 3, 7.5 % 2, 2 ** 0.5 * 2 ** 0.5]")
+            .unwrap()
+            .value;
+        assert_eq!(
+            value.to_string(),
+            "[7, 9, -4, 512, 1, -1, 1.5, 2.0000000000000004]"
+        );
+
+        let errors = lower_src("[1 % 0, 2 ** -1, 10 ** 30]").unwrap_err();
+        let msgs = messages(&errors);
+        assert_eq!(
+            msgs,
+            [
+                "remainder with a divisor of zero",
+                "negative exponent `-1` in an integer power",
+                "integer overflow in `10 ** 30`",
+            ]
+        );
+    }
+
+    #[test]
+    fn integer_limits() {
+        let value =
+            lower_src("[-9223372036854775808, 9223372036854775807, -9223372036854775808 % -1]")
+                .unwrap()
+                .value;
+        assert_eq!(
+            value.to_string(),
+            "[-9223372036854775808, 9223372036854775807, 0]"
+        );
+
+        let errors = lower_src(
+            r#"[9223372036854775808, 9223372036854775807 + 1, -9223372036854775807 - 2, 4611686018427387904 * 2, -9223372036854775808 / -1, -(-9223372036854775808), 1 / 0, "a" * -1, "ab" * 9223372036854775807]"#,
+        )
+        .unwrap_err();
+        let msgs = messages(&errors);
+        assert_eq!(
+            msgs,
+            [
+                "integer `9223372036854775808` is out of range, integers are between -9223372036854775808 and 9223372036854775807",
+                "integer overflow in `9223372036854775807 + 1`",
+                "integer overflow in `-9223372036854775807 - 2`",
+                "integer overflow in `4611686018427387904 * 2`",
+                "integer overflow in `-9223372036854775808 / -1`",
+                "integer overflow in `-(-9223372036854775808)`",
+                "division by zero",
+                "cannot repeat a string a negative number of times: -1",
+                "repeated string is too long, at most 16777216 bytes are allowed",
+            ]
+        );
+    }
+
+    #[test]
+    fn recursion_is_cut_off() {
+        let src = "fn f(x) { [f(x), f(x)] } { a: f(1) }";
+        let errors = lower_src(src).unwrap_err();
+        let msgs = messages(&errors);
+        assert_eq!(
+            msgs,
+            ["infinite recursion: `f` is called again with the same arguments"]
+        );
+        assert_eq!(&src[errors[0].span.range], "f(x)");
+
+        let src = "fn a(x) { b(x) } fn b(x) { a(x) } a(1)";
+        let errors = lower_src(src).unwrap_err();
+        assert_eq!(
+            labels(&errors[0], src),
+            [
+                ("a(1)", "`a` called here"),
+                ("b(x)", "`b` called here"),
+                ("a(x)", "`a` called here"),
+            ]
+        );
+
+        let src = "fn count(n) { count(n + 1) } [count(0)]";
+        let errors = lower_src(src).unwrap_err();
+        assert_eq!(
+            errors[0].msg,
+            "calls nested too deeply calling `count`, at most 64 are allowed"
+        );
+        assert_eq!(
+            labels(&errors[0], src),
+            [
+                ("count(0)", "`count` called here"),
+                ("count(n + 1)", "`count` called here 63 times"),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_show_the_calls_leading_to_them() {
+        let src = r#"fn double(x) { x * 2 } fn twice(x) { double(x) }
+[double(1), twice(2), twice(null), double(3)]"#;
+        let errors = lower_src(src).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "invalid multiplication operands");
+        assert_eq!(
+            labels(&errors[0], src),
+            [
+                ("twice(null)", "`twice` called here"),
+                ("double(x)", "`double` called here"),
+            ]
+        );
+
+        // arguments are lowered where the call is, outside of it
+        let errors = lower_src("fn id(x) { x } id(1 / 0)").unwrap_err();
+        assert!(errors[0].labels.is_empty());
+    }
+
+    #[test]
+    fn call_depth_limit() {
+        let src = "fn f(x) { x } fn g(x) { f(x) } fn h(x) { g(x) } h(1)";
+        let limits = |max_call_depth| Limits { max_call_depth };
+        assert_eq!(
+            lower_src_with(src, limits(3)).unwrap().value,
+            AwlycValue::Int(1)
+        );
+        let errors = lower_src_with(src, limits(2)).unwrap_err();
+        assert_eq!(
+            errors[0].msg,
+            "calls nested too deeply calling `f`, at most 2 are allowed"
+        );
+        assert_eq!(errors[0].labels.len(), 2);
+    }
+
+    #[test]
+    fn named_arguments_and_defaults() {
+        let src = r#"fn project(name, docs = null, tags: [string] = []) { { name, docs, tags, } }
+[project("a"), project("b", tags: ["x"]), project(docs: "d", name: "c")]"#;
+        assert_eq!(
+            lower_src(src).unwrap().value.to_string(),
+            r#"[{ name: "a", docs: null, tags: [] }, { name: "b", docs: null, tags: ["x"] }, { name: "c", docs: "d", tags: [] }]"#
+        );
+
+        let errors = lower_src(r#"fn f(x: int = 1) { x } fn g(x = g()) { x } [f(x: "a"), g()]"#)
+            .unwrap_err();
+        let msgs = messages(&errors);
+        assert_eq!(
+            msgs,
+            [
+                "mismatched types: expected `int`, found `string`",
+                "infinite recursion: `g` is called again with the same arguments",
+            ]
+        );
+    }
+
+    #[test]
+    fn variadics_and_spreads() {
+        let src = r#"fn tags(...items) { items } fn f(a, b, c) { [a, b, c] } fn xs() { ["x", "y"] }
+[tags(), tags("a", "b"), tags(...xs()), f(1, ...[2, 3]), f(...[1, 2], c: 3)]"#;
+        assert_eq!(
+            lower_src(src).unwrap().value.to_string(),
+            r#"[[], ["a", "b"], ["x", "y"], [1, 2, 3], [1, 2, 3]]"#
+        );
+
+        let errors = lower_src("fn f(a, b) { a } [f(...[1]), f(1, ...[2, 3]), f(...[1], a: 2)]")
+            .unwrap_err();
+        let msgs = messages(&errors);
+        assert_eq!(
+            msgs,
+            [
+                "incorrect number of arguments supplied to `f`: expected 2, found 1",
+                "incorrect number of arguments supplied to `f`: expected 2, found 3",
+                "argument `a` supplied to `f` more than once",
+                "missing argument `b` in call to `f`",
+            ]
+        );
+    }
+
+    // inputs that once crashed the parser or the evaluator, kept next to the fuzz target
+    #[test]
+    fn fuzz_corpus() {
+        let dir = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../fuzz/corpus/parse_and_lower"
+        );
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let src = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            let file_id = FileId(SmolStr::from("main"));
+            let mut expr_arena = Arena::default();
+            let (module, errors) = parse(&src, &mut expr_arena, file_id.clone());
+            let expr = match module.expr {
+                Some(expr) if errors.is_empty() => expr,
+                _ => continue,
+            };
+            let modules = HashMap::from([(file_id, module)]);
+            let (resolutions, errors) = resolve(&modules, &expr_arena);
+            if errors.is_empty() {
+                let _ = awlyc_hir::ty::check(&modules, &expr_arena, &resolutions);
+                let _ = lower_expr(expr, &modules, &expr_arena, &resolutions, Limits::default());
+            }
+        }
+    }
+
+    #[test]
+    fn annotations_checked_at_call_time() {
+        // `[any]` arguments can't be checked statically
+        let errors = lower_src(
+            r#"fn total(xs: [int]) -> int { 0 } fn id(x) -> string { x } [total([1, "2"]), total([1, 2]), id(1)]"#,
+        )
+        .unwrap_err();
+        let msgs = messages(&errors);
+        assert_eq!(
+            msgs,
+            [
+                "mismatched types at `[1]`: expected `int`, found `string`",
+                "mismatched types: expected `string`, found `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn schema_validation() {
+        let src = r#"
+            schema Topic { name: string }
+            schema Project { name: string, repo: string, topics: [Topic] }
+            fn topic(name) { { name: name } }
+            fn project(name, topics) -> Project { { name: name, topics: topics } }
+            project("awlyc", [topic("parsing"), topic(1)])"#;
+        let errors = lower_src(src).unwrap_err();
+        let errors = located(&errors, src);
+        assert_eq!(
+            errors,
+            [
+                (
+                    "missing field `repo` in `Project`",
+                    "{ name: name, topics: topics }"
+                ),
+                (
+                    "mismatched types at `topics[1].name`: expected `string`, found `int`",
+                    "1"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn recursive_schemas() {
+        let src = r#"schema Tree { name: string, children: [Tree] }
+            fn leaf(name) -> Tree { { name: name, children: [] } }
+            fn tree() -> Tree { { name: "root", children: [leaf("a"), leaf(1)] } }
+            tree()"#;
+        let errors = lower_src(src).unwrap_err();
+        let msgs = messages(&errors);
+        assert_eq!(
+            msgs,
+            ["mismatched types at `name`: expected `string`, found `int`"]
+        );
+
+        // nested values are checked against the schema as deeply as they go
+        let src = r#"schema Tree { name: string, children: [Tree] }
+            fn t() -> Tree: { name: "root", children: [{ name: 1, children: [{ nope: 2 }] }] }
+            t()"#;
+        let errors = lower_src(src).unwrap_err();
+        let errors = located(&errors, src);
+        assert_eq!(
+            errors,
+            [
+                (
+                    "missing field `children[0].children[0].children` in `Tree`",
+                    "{ nope: 2 }"
+                ),
+                (
+                    "missing field `children[0].children[0].name` in `Tree`",
+                    "{ nope: 2 }"
+                ),
+                (
+                    "unknown field `children[0].children[0].nope` in `Tree`",
+                    "{ nope: 2 }"
+                ),
+                (
+                    "mismatched types at `children[0].name`: expected `string`, found `int`",
+                    "1"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn record_field_order() {
+        use crate::serialize::to_string;
+        use serde::Serialize;
+
+        let src = r#"{ zeta: 1, alpha: { y: [], x: null }, mid: "m" }"#;
+        let value = lower_src(src).unwrap().value;
+        assert_eq!(value.to_string(), src);
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"zeta":1,"alpha":{"y":[],"x":null},"mid":"m"}"#
+        );
+
+        #[derive(Serialize)]
+        struct Page {
+            title: &'static str,
+            author: &'static str,
+            draft: bool,
+        }
+        let page = Page {
+            title: "a",
+            author: "b",
+            draft: false,
+        };
+        assert_eq!(
+            to_string(&page).unwrap(),
+            r#"{ title: "a", author: "b", draft: false }"#
+        );
+
+        // order doesn't matter for equality
+        assert_eq!(crate::awlyc!({ a: 1, b: 2 }), crate::awlyc!({ b: 2, a: 1 }));
+        assert_ne!(crate::awlyc!({ a: 1, b: 2 }), crate::awlyc!({ b: 1, a: 2 }));
+    }
+
+    #[test]
+    fn spread_and_merge() {
+        let src = r#"
+            fn base() { { host: "localhost", port: 80, tls: { cert: "a", key: "b" }, tags: ["x"] } }
+            [
+                { ...base(), port: 8080 },
+                { ...base(), ...{ port: 1, tags: [] }, name: "n" },
+                { ...base() } & { tls: { key: "c" }, tags: ["y"] } & { host: null }
+            ]"#;
+        let value = lower_src(src).unwrap().value;
+        assert_eq!(
+            value.to_string(),
+            r#"[{ host: "localhost", port: 8080, tls: { cert: "a", key: "b" }, tags: ["x"] }, { host: "localhost", port: 1, tls: { cert: "a", key: "b" }, tags: [], name: "n" }, { host: null, port: 80, tls: { cert: "a", key: "c" }, tags: ["y"] }]"#
+        );
+
+        // entries apply in source order, so a spread overrides the fields before it
+        let src = r#"
+            fn base() { { host: "localhost", port: 80 } }
+            { port: 8080, ...base(), host: "example.com" }"#;
+        let value = lower_src(src).unwrap().value;
+        assert_eq!(value.to_string(), r#"{ port: 80, host: "example.com" }"#);
+
+        let src = r#"fn f(x) { [{ ...x }, x & { a: 1 }] } [f({}), f(1)]"#;
+        let errors = lower_src(src).unwrap_err();
+        let errors = located(&errors, src);
+        assert_eq!(
+            errors,
+            [
+                ("only records can be spread, found `int`", "x"),
+                (
+                    "invalid merge operands: `int` & `{ a: int }`",
+                    "x & { a: 1 }"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn field_access_and_indexing() {
+        let src = r#"
+            fn page(title) { { title: title, tags: ["a", "b"], "last-edit": { by: "c" } } }
+            [page("x").title, page("x").tags[1], page("x")["last-edit"].by]"#;
+        let value = lower_src(src).unwrap();
+        assert_eq!(value.value, crate::awlyc!(["x", "b", "c"]));
+        // the parts keep the spans of where they're written
+        assert_eq!(&src[value.spans.elements[1].span.range], "\"b\"");
+
+        let src = r#"fn page(x) { { title: "a", tags: x } } [page([]).titel, page([1]).tags[3], page([]).tags[-1]]"#;
+        let errors = lower_src(src).unwrap_err();
+        assert_eq!(
+            located(&errors, src),
+            [
+                (
+                    "no field `titel` on `{ tags: [any], title: string }`",
+                    "titel"
+                ),
+                (
+                    "index out of bounds: the length is 1 but the index is 3",
+                    "3"
+                ),
+                (
+                    "index out of bounds: the length is 0 but the index is -1",
+                    "-1"
+                ),
+            ]
+        );
+        assert_eq!(
+            labels(&errors[0], src),
+            [("titel", "did you mean `title`?")]
+        );
+        assert!(errors[1..].iter().all(|e| e.labels.is_empty()));
+    }
 }
@@ -0,0 +1,112 @@
+/// Construct an `AwlycValue` with awlyc syntax
+/// Keys are identifiers or string literals, anything that isn't `null`, an array or a record is a
+/// Rust expression converted with `AwlycValue::from`
+///
+/// ```
+/// # use awlyc_values::awlyc;
+/// let stars = 3;
+/// let page = awlyc!({ title: "a", "last-edit": null, stars: stars + 1, tags: ["b", "c"] });
+/// assert_eq!(page["tags"][1], "c");
+/// ```
+#[macro_export]
+macro_rules! awlyc {
+    // elements of an array, collected between the brackets
+    (@array [$($els:expr,)*]) => {
+        ::std::vec![$($els,)*]
+    };
+    (@array [$($els:expr,)*] , $($rest:tt)*) => {
+        $crate::awlyc!(@array [$($els,)*] $($rest)*)
+    };
+    (@array [$($els:expr,)*] null $($rest:tt)*) => {
+        $crate::awlyc!(@array [$($els,)* $crate::awlyc!(null),] $($rest)*)
+    };
+    (@array [$($els:expr,)*] [ $($arr:tt)* ] $($rest:tt)*) => {
+        $crate::awlyc!(@array [$($els,)* $crate::awlyc!([$($arr)*]),] $($rest)*)
+    };
+    (@array [$($els:expr,)*] { $($rec:tt)* } $($rest:tt)*) => {
+        $crate::awlyc!(@array [$($els,)* $crate::awlyc!({$($rec)*}),] $($rest)*)
+    };
+    (@array [$($els:expr,)*] $next:expr , $($rest:tt)*) => {
+        $crate::awlyc!(@array [$($els,)* $crate::awlyc!($next),] , $($rest)*)
+    };
+    (@array [$($els:expr,)*] $last:expr) => {
+        $crate::awlyc!(@array [$($els,)* $crate::awlyc!($last),])
+    };
+
+    // fields of a record, collected as (key, value) pairs
+    (@record [$($fields:expr,)*]) => {
+        ::std::vec![$($fields,)*]
+    };
+    (@record [$($fields:expr,)*] , $($rest:tt)*) => {
+        $crate::awlyc!(@record [$($fields,)*] $($rest)*)
+    };
+    (@record [$($fields:expr,)*] $key:ident : $($rest:tt)*) => {
+        $crate::awlyc!(@field [$($fields,)*] (stringify!($key)) $($rest)*)
+    };
+    (@record [$($fields:expr,)*] $key:literal : $($rest:tt)*) => {
+        $crate::awlyc!(@field [$($fields,)*] ($key) $($rest)*)
+    };
+    (@field [$($fields:expr,)*] ($key:expr) null $($rest:tt)*) => {
+        $crate::awlyc!(@record [$($fields,)* ($key.into(), $crate::awlyc!(null)),] $($rest)*)
+    };
+    (@field [$($fields:expr,)*] ($key:expr) [ $($arr:tt)* ] $($rest:tt)*) => {
+        $crate::awlyc!(@record [$($fields,)* ($key.into(), $crate::awlyc!([$($arr)*])),] $($rest)*)
+    };
+    (@field [$($fields:expr,)*] ($key:expr) { $($rec:tt)* } $($rest:tt)*) => {
+        $crate::awlyc!(@record [$($fields,)* ($key.into(), $crate::awlyc!({$($rec)*})),] $($rest)*)
+    };
+    (@field [$($fields:expr,)*] ($key:expr) $value:expr , $($rest:tt)*) => {
+        $crate::awlyc!(@record [$($fields,)* ($key.into(), $crate::awlyc!($value)),] , $($rest)*)
+    };
+    (@field [$($fields:expr,)*] ($key:expr) $value:expr) => {
+        $crate::awlyc!(@record [$($fields,)* ($key.into(), $crate::awlyc!($value)),])
+    };
+
+    (null) => {
+        $crate::AwlycValue::Null
+    };
+    ([ $($tt:tt)* ]) => {
+        $crate::AwlycValue::Array($crate::awlyc!(@array [] $($tt)*))
+    };
+    ({ $($tt:tt)* }) => {
+        $crate::AwlycValue::Record(
+            $crate::awlyc!(@record [] $($tt)*).into_iter().collect()
+        )
+    };
+    ($other:expr) => {
+        $crate::AwlycValue::from($other)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{tests::lower_src, AwlycValue};
+
+    #[test]
+    fn awlyc_macro() {
+        let tags = vec!["x", "y"];
+        let value = crate::awlyc!({
+            title: "a",
+            "last-edit": null,
+            stars: 1 + 2,
+            ratio: 0.5,
+            draft: false,
+            tags: tags,
+            nested: [null, [1], { a: "b" }, -1],
+        });
+        let src = lower_src(
+            r#"{ title: "a", "last-edit": null, stars: 3, ratio: 0.5, draft: false, tags: ["x", "y"], nested: [null, [1], { a: "b" }, -1] }"#,
+        )
+        .unwrap();
+        assert_eq!(value, src.value);
+        assert_eq!(crate::awlyc!([]), AwlycValue::Array(vec![]));
+        assert_eq!(
+            crate::awlyc!({}),
+            AwlycValue::from(HashMap::<String, i64>::new())
+        );
+        assert_eq!(crate::awlyc!(Some(1)), 1);
+        assert_eq!(crate::awlyc!(None::<i64>), AwlycValue::Null);
+    }
+}
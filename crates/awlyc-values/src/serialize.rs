@@ -0,0 +1,552 @@
+use std::fmt;
+
+use indexmap::IndexMap;
+
+use serde::{ser, Serialize};
+use smol_str::SmolStr;
+
+use crate::AwlycValue;
+
+/// Error produced when a Rust value can't be written as awlyc
+#[derive(Debug)]
+pub struct Error {
+    pub msg: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: fmt::Display,
+    {
+        Error {
+            msg: msg.to_string(),
+        }
+    }
+}
+
+/// Values are serialized as plain data, e.g. `Int(3)` is just `3`
+impl Serialize for AwlycValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        match self {
+            AwlycValue::Null => serializer.serialize_unit(),
+            AwlycValue::Bool(v) => serializer.serialize_bool(*v),
+            AwlycValue::Int(v) => serializer.serialize_i64(*v),
+            AwlycValue::Float(v) => serializer.serialize_f64(*v),
+            AwlycValue::String(v) => serializer.serialize_str(v),
+            AwlycValue::Array(v) => serializer.collect_seq(v),
+            AwlycValue::Record(v) => serializer.collect_map(v),
+        }
+    }
+}
+
+/// Convert a Rust value into an awlyc value
+pub fn to_value<T>(value: &T) -> Result<AwlycValue, Error>
+where
+    T: ?Sized + Serialize,
+{
+    value.serialize(ValueSerializer)
+}
+
+/// Write a Rust value as awlyc source on a single line
+pub fn to_string<T>(value: &T) -> Result<String, Error>
+where
+    T: ?Sized + Serialize,
+{
+    Ok(to_value(value)?.to_string())
+}
+
+/// Write a Rust value as awlyc source, with arrays and records spread over multiple lines
+pub fn to_string_pretty<T>(value: &T) -> Result<String, Error>
+where
+    T: ?Sized + Serialize,
+{
+    Ok(format!("{:#}", to_value(value)?))
+}
+
+fn int<T>(v: T) -> Result<AwlycValue, Error>
+where
+    T: Copy + fmt::Display + TryInto<i64>,
+{
+    v.try_into().map(AwlycValue::Int).map_err(|_| Error {
+        msg: format!(
+            "integer `{}` is too large for awlyc (at most {})",
+            v,
+            i64::MAX
+        ),
+    })
+}
+
+/// Serializes a Rust value into an `AwlycValue`
+/// Enums are externally tagged, unit variants are strings and other variants are records with a
+/// single key, the variant's name
+pub struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = AwlycValue;
+    type Error = Error;
+
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeRecord;
+    type SerializeStruct = SerializeRecord;
+    type SerializeStructVariant = SerializeRecord;
+
+    fn serialize_bool(self, v: bool) -> Result<AwlycValue, Error> {
+        Ok(AwlycValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<AwlycValue, Error> {
+        int(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<AwlycValue, Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<AwlycValue, Error> {
+        if v.is_finite() {
+            Ok(AwlycValue::Float(v))
+        } else {
+            Err(Error {
+                msg: format!("`{}` can't be written as an awlyc float", v),
+            })
+        }
+    }
+
+    fn serialize_char(self, v: char) -> Result<AwlycValue, Error> {
+        Ok(AwlycValue::from(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<AwlycValue, Error> {
+        Ok(AwlycValue::String(SmolStr::from(v)))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<AwlycValue, Error> {
+        Ok(AwlycValue::Array(
+            v.iter().map(|b| AwlycValue::Int((*b).into())).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<AwlycValue, Error> {
+        Ok(AwlycValue::Null)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<AwlycValue, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<AwlycValue, Error> {
+        Ok(AwlycValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<AwlycValue, Error> {
+        Ok(AwlycValue::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<AwlycValue, Error> {
+        Ok(AwlycValue::String(SmolStr::from(variant)))
+    }
+
+    fn serialize_newtype_struct<T>(self, _: &'static str, value: &T) -> Result<AwlycValue, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<AwlycValue, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Ok(AwlycValue::Record(IndexMap::from([(
+            SmolStr::from(variant),
+            value.serialize(self)?,
+        )])))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, Error> {
+        Ok(SerializeArray {
+            variant: None,
+            els: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, Error> {
+        Ok(SerializeArray {
+            variant: Some(variant),
+            els: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<SerializeRecord, Error> {
+        Ok(SerializeRecord {
+            variant: None,
+            fields: IndexMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<SerializeRecord, Error> {
+        self.serialize_map(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> Result<SerializeRecord, Error> {
+        Ok(SerializeRecord {
+            variant: Some(variant),
+            fields: IndexMap::new(),
+            key: None,
+        })
+    }
+}
+
+/// Wrap the content of a variant in a record keyed by the variant's name
+fn tagged(variant: Option<&'static str>, value: AwlycValue) -> AwlycValue {
+    match variant {
+        Some(variant) => AwlycValue::Record(IndexMap::from([(SmolStr::from(variant), value)])),
+        None => value,
+    }
+}
+
+pub struct SerializeArray {
+    variant: Option<&'static str>,
+    els: Vec<AwlycValue>,
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = AwlycValue;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.els.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<AwlycValue, Error> {
+        Ok(tagged(self.variant, AwlycValue::Array(self.els)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = AwlycValue;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<AwlycValue, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = AwlycValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<AwlycValue, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = AwlycValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<AwlycValue, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeRecord {
+    variant: Option<&'static str>,
+    fields: IndexMap<SmolStr, AwlycValue>,
+    /// Key of the entry whose value is serialized next
+    key: Option<SmolStr>,
+}
+
+impl ser::SerializeMap for SerializeRecord {
+    type Ok = AwlycValue;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        match to_value(key)? {
+            AwlycValue::String(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            key => Err(Error {
+                msg: format!("record keys must be strings, found `{}`", key),
+            }),
+        }
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = self
+            .key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        self.fields.insert(key, to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<AwlycValue, Error> {
+        Ok(tagged(self.variant, AwlycValue::Record(self.fields)))
+    }
+}
+
+impl ser::SerializeStruct for SerializeRecord {
+    type Ok = AwlycValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.fields.insert(SmolStr::from(key), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<AwlycValue, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeRecord {
+    type Ok = AwlycValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<AwlycValue, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{to_string, to_string_pretty, to_value};
+    use crate::{deserialize::from_spanned_val, tests::lower_src, AwlycValue};
+
+    #[test]
+    fn serialize() {
+        assert_eq!(
+            to_string(&("a \"b\"\n\\", 1.0_f32, -2.5, true, None::<u8>, 'c')).unwrap(),
+            r#"["a \"b\"\n\\", 1.0, -2.5, true, null, "c"]"#
+        );
+        assert_eq!(
+            to_string(&HashMap::from([("fn", 1)])).unwrap(),
+            r#"{ "fn": 1 }"#
+        );
+        assert_eq!(
+            to_string(&HashMap::from([("a-b", 1)])).unwrap(),
+            r#"{ "a-b": 1 }"#
+        );
+        assert_eq!(
+            to_string_pretty(&HashMap::from([("pages", ["a"])])).unwrap(),
+            "{\n  pages: [\n    \"a\",\n  ],\n}"
+        );
+        assert_eq!(
+            to_string(&u64::MAX).unwrap_err().msg,
+            "integer `18446744073709551615` is too large for awlyc (at most 9223372036854775807)"
+        );
+        assert_eq!(
+            to_string(&HashMap::from([(1, 2)])).unwrap_err().msg,
+            "record keys must be strings, found `1`"
+        );
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Shape {
+            Point,
+            Circle(f64),
+            Line(i32, i32),
+            Rect { w: u32, h: u32 },
+        }
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Config {
+            name: String,
+            debug: bool,
+            ratio: f64,
+            shapes: Vec<Shape>,
+            parent: Option<Box<Config>>,
+            env: HashMap<String, String>,
+        }
+
+        let config = Config {
+            name: "tab\tquote\"backslash\\ \u{7} ✓".to_string(),
+            debug: false,
+            ratio: 3.0,
+            shapes: vec![
+                Shape::Point,
+                Shape::Circle(0.25),
+                Shape::Line(-1, 2),
+                Shape::Rect { w: 2, h: 3 },
+            ],
+            parent: Some(Box::new(Config {
+                name: "parent".to_string(),
+                debug: true,
+                ratio: -0.5,
+                shapes: vec![],
+                parent: None,
+                env: HashMap::new(),
+            })),
+            env: HashMap::from([
+                ("HOME".to_string(), "/root".to_string()),
+                ("my key".to_string(), "".to_string()),
+                ("null".to_string(), "x".to_string()),
+            ]),
+        };
+        for src in [
+            to_string(&config).unwrap(),
+            to_string_pretty(&config).unwrap(),
+        ] {
+            let value = lower_src(&src).unwrap();
+            assert_eq!(
+                from_spanned_val::<Config>(&value).unwrap(),
+                config,
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn value_serialize() {
+        let json = r#"{"title":"a","stars":3,"ratio":0.5,"draft":true,"parent":null,"tags":[]}"#;
+        let value: AwlycValue = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            serde_json::to_value(&value).unwrap(),
+            serde_json::from_str::<serde_json::Value>(json).unwrap()
+        );
+
+        // through awlyc's own serializer
+        let value = lower_src(r#"[1, 2.5, "a", null, true, { a: [] }]"#)
+            .unwrap()
+            .value;
+        assert_eq!(to_value(&value).unwrap().to_string(), value.to_string());
+        assert_eq!(
+            to_string(&value).unwrap(),
+            r#"[1, 2.5, "a", null, true, { a: [] }]"#
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::HashMap, mem};
+
+use indexmap::{map::Entry, IndexMap};
+
+use awlyc_error::Span;
+use awlyc_hir::ty::Segment;
+use smol_str::SmolStr;
+
+use crate::AwlycValue;
+
+/// Spans of the expressions that produced a value and each of its parts, shaped like the value
+#[derive(Debug, Clone)]
+pub struct ValueSpans {
+    pub span: Span,
+    /// Spans of the elements of an array value
+    pub elements: Vec<ValueSpans>,
+    /// Spans of the fields of a record value
+    pub fields: HashMap<SmolStr, ValueSpans>,
+}
+
+impl ValueSpans {
+    pub fn new(span: Span) -> Self {
+        Self {
+            span,
+            elements: vec![],
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Spans of the part at `path`, or of the innermost part on the way there
+    pub fn at(&self, path: &[Segment]) -> &ValueSpans {
+        let next = match path.first() {
+            Some(Segment::Field(field)) => self.fields.get(field),
+            Some(Segment::Index(idx)) => self.elements.get(*idx),
+            None => None,
+        };
+        match next {
+            Some(next) => next.at(&path[1..]),
+            None => self,
+        }
+    }
+}
+
+/// Value produced by the evaluator, it remembers where each of its parts comes from so that
+/// problems found later, e.g. during deserialization, can be reported in the source
+#[derive(Debug, Clone)]
+pub struct SpannedValue {
+    pub value: AwlycValue,
+    pub spans: ValueSpans,
+}
+
+impl SpannedValue {
+    /// A value without parts, or one whose parts all come from the same expression
+    pub fn new(value: AwlycValue, span: Span) -> Self {
+        Self {
+            value,
+            spans: ValueSpans::new(span),
+        }
+    }
+
+    pub fn array(els: Vec<SpannedValue>, span: Span) -> Self {
+        let (values, spans) = els.into_iter().map(|el| (el.value, el.spans)).unzip();
+        Self {
+            value: AwlycValue::Array(values),
+            spans: ValueSpans {
+                elements: spans,
+                ..ValueSpans::new(span)
+            },
+        }
+    }
+
+    pub fn record(fields: Vec<(SmolStr, SpannedValue)>, span: Span) -> Self {
+        let mut values = IndexMap::new();
+        let mut spans = HashMap::new();
+        for (k, v) in fields {
+            values.insert(k.clone(), v.value);
+            spans.insert(k, v.spans);
+        }
+        Self {
+            value: AwlycValue::Record(values),
+            spans: ValueSpans {
+                fields: spans,
+                ..ValueSpans::new(span)
+            },
+        }
+    }
+
+    /// Fields of a record value along with their spans, `None` for any other value
+    pub fn into_fields(self) -> Option<Vec<(SmolStr, SpannedValue)>> {
+        let SpannedValue { value, mut spans } = self;
+        match value {
+            AwlycValue::Record(fields) => Some(
+                fields
+                    .into_iter()
+                    .map(|(k, value)| {
+                        let spans = spans
+                            .fields
+                            .remove(&k)
+                            .unwrap_or_else(|| ValueSpans::new(spans.span.clone()));
+                        (k, SpannedValue { value, spans })
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Elements of an array value along with their spans, `None` for any other value
+    pub fn into_elements(self) -> Option<Vec<SpannedValue>> {
+        let SpannedValue { value, spans } = self;
+        match value {
+            AwlycValue::Array(els) => {
+                let mut el_spans = spans.elements.into_iter();
+                Some(
+                    els.into_iter()
+                        .map(|value| SpannedValue {
+                            value,
+                            spans: el_spans
+                                .next()
+                                .unwrap_or_else(|| ValueSpans::new(spans.span.clone())),
+                        })
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// Element of an array value along with its spans, `None` for any other value
+    pub fn into_element(self, idx: usize) -> Option<SpannedValue> {
+        let SpannedValue { value, mut spans } = self;
+        match value {
+            AwlycValue::Array(mut els) if idx < els.len() => {
+                let spans = if idx < spans.elements.len() {
+                    spans.elements.swap_remove(idx)
+                } else {
+                    ValueSpans::new(spans.span)
+                };
+                Some(SpannedValue {
+                    value: els.swap_remove(idx),
+                    spans,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Deep merge `other` into this value, see `BinopKind::Merge`
+    /// Merged records get `span`, every other part keeps the spans of the value it comes from
+    pub fn merge(self, other: SpannedValue, span: Span) -> SpannedValue {
+        let (lhs, rhs) = match (&self.value, &other.value) {
+            (AwlycValue::Record(_), AwlycValue::Record(_)) => {
+                (self.into_fields().unwrap(), other.into_fields().unwrap())
+            }
+            _ => return other,
+        };
+        let mut fields: IndexMap<_, _> = lhs.into_iter().collect();
+        for (k, rhs) in rhs {
+            match fields.entry(k) {
+                Entry::Occupied(mut entry) => {
+                    let span = rhs.spans.span.clone();
+                    let placeholder = SpannedValue::new(AwlycValue::Null, span.clone());
+                    let lhs = mem::replace(entry.get_mut(), placeholder);
+                    *entry.get_mut() = lhs.merge(rhs, span);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(rhs);
+                }
+            }
+        }
+        SpannedValue::record(fields.into_iter().collect(), span)
+    }
+}
@@ -1,95 +1,213 @@
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use awlyc_error::{Diagnostic, DiagnosticKind, DiagnosticReporter, FileId, Span};
+use awlyc_hir::{file_id, resolve, resolve_import, ty::check};
 use awlyc_parser::{
     ast::{Expr, Spanned},
     parse, Module,
 };
-use awlyc_values::{deserialize::from_awlyc_val, lower};
+use awlyc_values::{deserialize::from_spanned_val, lower, SpannedValue};
 use la_arena::Arena;
 use serde::de::DeserializeOwned;
 use smol_str::SmolStr;
 use text_size::TextRange;
 
-fn canonicalize_path(path: &str, diagnostic_reporter: &mut DiagnosticReporter) -> PathBuf {
-    let absolute_path = fs::canonicalize(path);
-    match absolute_path {
-        Err(err) => {
-            diagnostic_reporter.report(&Diagnostic {
-                kind: DiagnosticKind::Error,
-                msg: format!("could not open file `{}`: {}", path, err),
-                span: Span {
-                    range: TextRange::new(0.into(), 0.into()),
-                    file_id: FileId(SmolStr::from("")),
-                },
-            });
-            panic!("could not open file");
-        }
-        Ok(absolute_path) => absolute_path,
-    }
+pub mod repl;
+pub mod schema;
+
+pub use awlyc_derive::Schema;
+pub use awlyc_hir::ty::Ty;
+pub use awlyc_values::{
+    awlyc,
+    serialize::{to_string, to_string_pretty},
+    AwlycValue, Limits,
+};
+pub use schema::Schema;
+
+// lets the derive macro's `::awlyc` paths resolve inside this crate
+extern crate self as awlyc;
+
+fn canonicalize_path(path: &Path) -> Result<PathBuf, Diagnostic> {
+    fs::canonicalize(path).map_err(|err| Diagnostic {
+        kind: DiagnosticKind::Error,
+        msg: format!("could not open file `{}`: {}", path.display(), err),
+        span: Span {
+            range: TextRange::new(0.into(), 0.into()),
+            file_id: FileId(SmolStr::from("")),
+        },
+        labels: vec![],
+    })
 }
 
-/// Apologies to anyone reading this
-fn parse_file(
-    path: &str,
+/// Parse a file and, recursively, every file it imports
+/// Returns the id of the parsed file, parse errors of all files are pushed onto `errors`
+pub(crate) fn parse_file(
+    path: &Path,
     modules: &mut HashMap<FileId, Module>,
     expr_arena: &mut Arena<Spanned<Expr>>,
     diagnostic_reporter: &mut DiagnosticReporter,
-) {
-    let mut path = canonicalize_path(path, diagnostic_reporter);
-    let file_id = FileId(SmolStr::from(path.to_str().unwrap()));
+    errors: &mut Vec<Diagnostic>,
+) -> Result<FileId, Diagnostic> {
+    let path = canonicalize_path(path)?;
+    let file_id = file_id(&path)?;
 
     // we've already parsed this file
     if modules.get(&file_id).is_some() {
-        return;
+        return Ok(file_id);
     }
 
-    let src = fs::read_to_string(&path).unwrap();
+    let src = fs::read_to_string(&path).map_err(|err| Diagnostic {
+        kind: DiagnosticKind::Error,
+        msg: format!("could not read file `{}`: {}", path.display(), err),
+        span: Span {
+            range: TextRange::new(0.into(), 0.into()),
+            file_id: FileId(SmolStr::from("")),
+        },
+        labels: vec![],
+    })?;
     diagnostic_reporter.add_file(file_id.0.clone(), src.clone());
-    let (module, errors) = parse(&src, expr_arena, file_id.clone());
-    for err in &errors {
-        diagnostic_reporter.report(err);
-    }
+    let (module, parse_errors) = parse(&src, expr_arena, file_id.clone());
+    errors.extend(parse_errors);
 
     let imports = module.imports.to_vec();
-    modules.insert(file_id, module); // must insert before looping over imports to prevent infinite recursion
+    modules.insert(file_id.clone(), module); // must insert before looping over imports to prevent infinite recursion
 
     for import in &imports {
-        path.pop();
-        path.push(import.path.as_str());
         parse_file(
-            path.to_str().unwrap(),
+            &resolve_import(&file_id, import),
             modules,
             expr_arena,
             diagnostic_reporter,
-        )
+            errors,
+        )?;
     }
+    Ok(file_id)
 }
 
-pub fn from_file<T>(path: &str) -> T
-where
-    T: DeserializeOwned,
-{
+/// How a file is evaluated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    pub limits: Limits,
+    /// Type check every function before evaluating the file, so mismatches are reported even in
+    /// functions that aren't called, and the file isn't evaluated if there are any
+    pub check_types: bool,
+}
+
+/// Evaluate a file and validate its value against `ty`, if there is one
+/// Every mismatch is reported at the expression that produced it, warnings are reported as they
+/// come up and all errors are returned
+fn eval_file(
+    path: &str,
+    ty: Option<&Ty>,
+    options: Options,
+    diagnostic_reporter: &mut DiagnosticReporter,
+) -> Result<SpannedValue, Vec<Diagnostic>> {
     let mut modules = HashMap::new();
     let mut expr_arena = Arena::default();
-    let mut diagnostic_reporter = DiagnosticReporter { files: vec![] };
-    parse_file(
-        path,
+    let mut errors = vec![];
+    let entry = parse_file(
+        Path::new(path),
         &mut modules,
         &mut expr_arena,
-        &mut diagnostic_reporter,
-    );
+        diagnostic_reporter,
+        &mut errors,
+    )
+    .map_err(|err| vec![err])?;
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
-    let res = lower(path, &modules, &expr_arena);
-    let value = match res {
-        Err(err) => {
-            diagnostic_reporter.report(&err);
+    let (resolutions, mut diagnostics) = resolve(&modules, &expr_arena);
+    if options.check_types {
+        diagnostics.extend(check(&modules, &expr_arena, &resolutions));
+    }
+    let (errors, warnings): (Vec<_>, Vec<_>) = diagnostics
+        .into_iter()
+        .partition(|d| d.kind == DiagnosticKind::Error);
+    for warning in &warnings {
+        diagnostic_reporter.report(warning);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let value = lower(&entry, &modules, &expr_arena, &resolutions, options.limits)?;
+    let ty = match ty {
+        Some(ty) => ty,
+        None => return Ok(value),
+    };
+
+    let errors: Vec<_> = value
+        .value
+        .mismatches(ty)
+        .iter()
+        .map(|mismatch| Diagnostic {
+            kind: DiagnosticKind::Error,
+            msg: mismatch.to_string(),
+            span: value.spans.at(&mismatch.path).span.clone(),
+            labels: vec![],
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(value)
+}
+
+/// Evaluate a file and deserialize its value into `T`
+pub fn from_file<T>(path: &str) -> T
+where
+    T: DeserializeOwned,
+{
+    load(path, None, Options::default())
+}
+
+/// Evaluate a file with `options` and deserialize its value into `T`
+pub fn from_file_with_options<T>(path: &str, options: Options) -> T
+where
+    T: DeserializeOwned,
+{
+    load(path, None, options)
+}
+
+/// Evaluate a file and deserialize its value into `T`
+/// The value is validated against the schema of `T` first, so that every mismatch is reported
+/// instead of only the first one serde runs into
+pub fn from_file_checked<T>(path: &str) -> T
+where
+    T: DeserializeOwned + Schema,
+{
+    load(path, Some(T::schema()), Options::default())
+}
+
+/// Evaluate a file with `options`, validate it against the schema of `T` and deserialize it
+pub fn from_file_checked_with_options<T>(path: &str, options: Options) -> T
+where
+    T: DeserializeOwned + Schema,
+{
+    load(path, Some(T::schema()), options)
+}
+
+fn load<T>(path: &str, ty: Option<Ty>, options: Options) -> T
+where
+    T: DeserializeOwned,
+{
+    let mut diagnostic_reporter = DiagnosticReporter { files: vec![] };
+    let value = match eval_file(path, ty.as_ref(), options, &mut diagnostic_reporter) {
+        Err(errors) => {
+            for err in &errors {
+                diagnostic_reporter.report(err);
+            }
             panic!("")
         }
         Ok(value) => value,
     };
 
-    match from_awlyc_val(&value) {
+    match from_spanned_val(&value) {
         Err(err) => {
             diagnostic_reporter.report(&err);
             panic!("")
@@ -100,9 +218,17 @@ where
 
 #[cfg(test)]
 mod tests {
+    use awlyc_error::DiagnosticReporter;
     use serde::Deserialize;
 
-    use crate::from_file;
+    use std::collections::HashMap;
+
+    use awlyc_hir::{resolve, ty::check};
+    use la_arena::Arena;
+
+    use crate::{
+        eval_file, from_file, from_file_checked, parse_file, AwlycValue, Options, Schema, Ty,
+    };
 
     #[test]
     fn basic() {
@@ -114,6 +240,111 @@ mod tests {
 
         let result: Project = from_file("../../examples/basic.awlyc");
 
-        println!("{:#?}", result);
+        assert_eq!(result.title, "Hi");
+        assert_eq!(result.author, "Test");
+    }
+
+    #[test]
+    fn checked() {
+        // fields serde ignores are accepted
+        #[derive(Debug, Deserialize, Schema)]
+        struct Page {
+            title: String,
+        }
+
+        let result: Page = from_file_checked("../../examples/basic.awlyc");
+        assert_eq!(result.title, "Hi");
+    }
+
+    #[test]
+    fn schema_mismatches() {
+        #[derive(Schema)]
+        #[allow(dead_code)]
+        struct Page {
+            #[serde(rename = "title")]
+            heading: i64,
+            tags: Vec<String>,
+            draft: Option<bool>,
+        }
+
+        #[derive(Schema)]
+        #[serde(deny_unknown_fields)]
+        #[allow(dead_code)]
+        struct StrictPage {
+            title: String,
+        }
+
+        let errors = |ty: &Ty| {
+            let mut reporter = DiagnosticReporter { files: vec![] };
+            let errors = eval_file(
+                "../../examples/basic.awlyc",
+                Some(ty),
+                Options::default(),
+                &mut reporter,
+            )
+            .unwrap_err();
+            errors.into_iter().map(|e| e.msg).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors(&Page::schema()),
+            [
+                "missing field `tags` in `Page`",
+                "mismatched types at `title`: expected `int`, found `string`",
+            ]
+        );
+        assert_eq!(
+            errors(&StrictPage::schema()),
+            ["unknown field `author` in `StrictPage`"]
+        );
+    }
+
+    #[test]
+    fn type_checking_is_opt_in() {
+        let path = std::env::temp_dir().join("awlyc_type_checking_is_opt_in.awlyc");
+        std::fs::write(&path, r#"fn unused() { "a" - 1 } { a: 1 }"#).unwrap();
+        let eval = |check_types| {
+            let options = Options {
+                check_types,
+                ..Options::default()
+            };
+            let mut reporter = DiagnosticReporter { files: vec![] };
+            eval_file(path.to_str().unwrap(), None, options, &mut reporter)
+        };
+        assert!(eval(false).is_ok());
+        let errors: Vec<_> = eval(true).unwrap_err().into_iter().map(|e| e.msg).collect();
+        assert_eq!(errors, ["invalid subtraction operands: `string` - `int`"]);
+    }
+
+    #[test]
+    fn dynamic_values() {
+        let value: AwlycValue = from_file("../../examples/basic.awlyc");
+        assert_eq!(value["title"], "Hi");
+        assert_eq!(
+            value.pointer("/author").and_then(AwlycValue::as_str),
+            Some("Test")
+        );
+    }
+
+    // the examples are what the README points people at, they have to keep working
+    #[test]
+    fn examples() {
+        for entry in std::fs::read_dir("../../examples").unwrap() {
+            let path = entry.unwrap().path();
+            let mut modules = HashMap::new();
+            let mut expr_arena = Arena::default();
+            let mut errors = vec![];
+            parse_file(
+                &path,
+                &mut modules,
+                &mut expr_arena,
+                &mut DiagnosticReporter { files: vec![] },
+                &mut errors,
+            )
+            .unwrap();
+            let (resolutions, resolve_errors) = resolve(&modules, &expr_arena);
+            errors.extend(resolve_errors);
+            errors.extend(check(&modules, &expr_arena, &resolutions));
+            assert!(errors.is_empty(), "{}: {:#?}", path.display(), errors);
+        }
     }
 }
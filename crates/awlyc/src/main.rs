@@ -0,0 +1,17 @@
+use std::{env, process};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("repl") => {
+            if let Err(err) = awlyc::repl::run() {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("usage: awlyc repl");
+            process::exit(1);
+        }
+    }
+}
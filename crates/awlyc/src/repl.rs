@@ -0,0 +1,464 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use awlyc_error::{Diagnostic, DiagnosticKind, DiagnosticReporter, FileId, Span};
+use awlyc_hir::{
+    resolve_import,
+    ty::{Ty, TypeChecker},
+    Resolutions, Resolver,
+};
+use awlyc_lexer::{lex, TokenKind};
+use awlyc_parser::{
+    ast::{Expr, ExprIdx, FnDecl, ImportDecl, SchemaDecl, Spanned},
+    parse, Module,
+};
+use awlyc_values::{lower_expr, AwlycValue, Limits};
+use la_arena::Arena;
+use smol_str::SmolStr;
+use text_size::TextRange;
+
+use crate::parse_file;
+
+/// Id of the module holding everything declared in the session
+const REPL_MODULE: &str = "<repl>";
+
+const HELP: &str = "\
+enter an expression to evaluate it, or an `fn`/`import`/`schema` declaration to add it to the session
+
+commands:
+  :load <file>  bring the functions and imports of a file into scope
+  :reload       re-read the loaded file and its imports from disk
+  :type <expr>  show the type of an expression, without evaluating it, or a function's signature
+  :help         show this message
+  :quit         exit the repl";
+
+/// A repl session
+/// Imports and functions are accumulated in a module of their own, every expression entered is
+/// evaluated in the context of that module
+pub struct Session {
+    modules: HashMap<FileId, Module>,
+    expr_arena: Arena<Spanned<Expr>>,
+    reporter: DiagnosticReporter,
+    /// Module declarations are added to, this is the loaded file's module after a `:load`
+    module_id: FileId,
+    /// File passed to `:load`
+    loaded: Option<PathBuf>,
+    /// Inputs that declared imports, functions or schemas, these are replayed on `:reload`
+    declarations: Vec<String>,
+    /// Number of inputs seen so far, used to give each input a unique file id
+    inputs: usize,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        let module = Module {
+            file_id: Self::repl_id(),
+            imports: vec![],
+            functions: vec![],
+            schemas: vec![],
+            expr: None,
+        };
+        Self {
+            modules: HashMap::from([(Self::repl_id(), module)]),
+            expr_arena: Arena::default(),
+            reporter: DiagnosticReporter { files: vec![] },
+            module_id: Self::repl_id(),
+            loaded: None,
+            declarations: vec![],
+            inputs: 0,
+        }
+    }
+
+    fn repl_id() -> FileId {
+        FileId(SmolStr::from(REPL_MODULE))
+    }
+
+    /// Declare the imports, functions and schemas in `src` and evaluate its expression, if it has one
+    pub fn eval(&mut self, src: &str) -> Result<Option<AwlycValue>, Vec<Diagnostic>> {
+        let module = self.parse_input(src)?;
+        if !module.imports.is_empty() || !module.functions.is_empty() || !module.schemas.is_empty()
+        {
+            // declarations with errors are rolled back so they don't break the rest of the session
+            let modules = self.modules.clone();
+            if let Err(errors) = self
+                .declare(module.imports, module.functions, module.schemas)
+                .and_then(|()| self.check(None).map(|_| ()))
+            {
+                self.modules = modules;
+                return Err(errors);
+            }
+            self.declarations.push(src.to_string());
+        }
+        module.expr.map(|expr| self.lower(expr)).transpose()
+    }
+
+    /// Infer the type of the expression in `src` without evaluating it
+    /// A function name, e.g. `f` or `alias.f`, gives the function's signature
+    pub fn type_of(&mut self, src: &str) -> Result<String, Vec<Diagnostic>> {
+        let module = self.parse_input(src)?;
+        match module.expr {
+            Some(expr) => {
+                if let Some(signature) = self.signature(expr)? {
+                    return Ok(signature);
+                }
+                let (_, ty) = self.check(Some(expr))?;
+                Ok(ty.unwrap().to_string())
+            }
+            None => Err(vec![Diagnostic {
+                kind: DiagnosticKind::Error,
+                msg: "expected an expression".to_string(),
+                span: Span {
+                    range: TextRange::new(0.into(), 0.into()),
+                    file_id: module.file_id,
+                },
+                labels: vec![],
+            }]),
+        }
+    }
+
+    /// Make a file the module of the session, bringing its imports and functions into scope
+    pub fn load(&mut self, path: &str) -> Result<(), Vec<Diagnostic>> {
+        self.rebuild(Some(PathBuf::from(path)))
+    }
+
+    /// Rebuild the session from scratch, re-reading every file from disk
+    pub fn reload(&mut self) -> Result<(), Vec<Diagnostic>> {
+        self.rebuild(self.loaded.clone())
+    }
+
+    /// Build a new session with `loaded` as its module and replay the declarations into it
+    /// The session is only replaced if that succeeds, so a failed `:load` or `:reload` keeps
+    /// everything declared so far
+    fn rebuild(&mut self, loaded: Option<PathBuf>) -> Result<(), Vec<Diagnostic>> {
+        let mut session = Session {
+            // errors of the new session are reported with the files it read
+            reporter: std::mem::replace(&mut self.reporter, DiagnosticReporter { files: vec![] }),
+            loaded,
+            inputs: self.inputs,
+            ..Session::new()
+        };
+        match session.replay(&self.declarations) {
+            Ok(()) => {
+                *self = session;
+                Ok(())
+            }
+            Err(errors) => {
+                self.reporter = session.reporter;
+                self.inputs = session.inputs;
+                Err(errors)
+            }
+        }
+    }
+
+    /// Parse the loaded file and declare `declarations` in a new session
+    fn replay(&mut self, declarations: &[String]) -> Result<(), Vec<Diagnostic>> {
+        if let Some(path) = self.loaded.clone() {
+            let mut errors = vec![];
+            self.module_id = parse_file(
+                &path,
+                &mut self.modules,
+                &mut self.expr_arena,
+                &mut self.reporter,
+                &mut errors,
+            )
+            .map_err(|err| vec![err])?;
+            if !errors.is_empty() {
+                return Err(errors);
+            }
+        }
+
+        for src in declarations {
+            let module = self.parse_input(src)?;
+            self.declare(module.imports, module.functions, module.schemas)?;
+            self.declarations.push(src.clone());
+        }
+        self.check(None).map(|_| ())
+    }
+
+    /// Report diagnostics produced by the session
+    pub fn report(&self, errors: &[Diagnostic]) {
+        for err in errors {
+            self.reporter.report(err);
+        }
+    }
+
+    fn parse_input(&mut self, src: &str) -> Result<Module, Vec<Diagnostic>> {
+        self.inputs += 1;
+        let file_id = FileId(SmolStr::from(format!("<repl:{}>", self.inputs)));
+        self.reporter.add_file(file_id.0.clone(), src.to_string());
+        let (module, errors) = parse(src, &mut self.expr_arena, file_id);
+        if errors.is_empty() {
+            Ok(module)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Add imports, functions and schemas to the session module
+    /// Declarations replace earlier ones with the same name
+    /// Imports entered in the repl are relative to the working directory, so they're made absolute
+    /// in case the session module is a loaded file
+    fn declare(
+        &mut self,
+        imports: Vec<ImportDecl>,
+        functions: Vec<FnDecl>,
+        schemas: Vec<SchemaDecl>,
+    ) -> Result<(), Vec<Diagnostic>> {
+        let mut errors = vec![];
+        let mut absolute_imports = vec![];
+        for import in imports {
+            let file_id = parse_file(
+                &resolve_import(&Self::repl_id(), &import),
+                &mut self.modules,
+                &mut self.expr_arena,
+                &mut self.reporter,
+                &mut errors,
+            )
+            .map_err(|err| vec![err])?;
+            absolute_imports.push(ImportDecl {
+                path: file_id.0,
+                ..import
+            });
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let module = self.modules.get_mut(&self.module_id).unwrap();
+        for import in absolute_imports {
+            module.imports.retain(|i| i.name.inner != import.name.inner);
+            module.imports.push(import);
+        }
+        for function in functions {
+            module
+                .functions
+                .retain(|f| f.name.inner != function.name.inner);
+            module.functions.push(function);
+        }
+        for schema in schemas {
+            module.schemas.retain(|s| s.name.inner != schema.name.inner);
+            module.schemas.push(schema);
+        }
+        Ok(())
+    }
+
+    /// Resolve and type check every module, and optionally an expression entered in the repl
+    /// Warnings are dropped, the session module would warn about unused imports on every input
+    fn check(&self, expr: Option<ExprIdx>) -> Result<(Resolutions, Option<Ty>), Vec<Diagnostic>> {
+        let errors = |diagnostics: Vec<Diagnostic>| -> Vec<Diagnostic> {
+            diagnostics
+                .into_iter()
+                .filter(|d| d.kind == DiagnosticKind::Error)
+                .collect()
+        };
+
+        let mut resolver = Resolver::new(&self.modules, &self.expr_arena);
+        resolver.resolve_modules();
+        if let Some(expr) = expr {
+            resolver.resolve_expr(&self.module_id, expr);
+        }
+        let (resolutions, diagnostics) = resolver.finish();
+        let resolve_errors = errors(diagnostics);
+        if !resolve_errors.is_empty() {
+            return Err(resolve_errors);
+        }
+
+        let mut checker = TypeChecker::new(&self.modules, &self.expr_arena, &resolutions);
+        checker.check_modules();
+        let ty = expr.map(|expr| checker.infer_expr(expr));
+        let type_errors = errors(checker.finish());
+        if !type_errors.is_empty() {
+            return Err(type_errors);
+        }
+        Ok((resolutions, ty))
+    }
+
+    /// Signature of the function `expr` names, if it's a path to one
+    fn signature(&self, expr: ExprIdx) -> Result<Option<String>, Vec<Diagnostic>> {
+        // `alias.f` isn't called, so it's parsed as the field `f` of `alias`
+        let path = match &self.expr_arena[expr].inner {
+            Expr::Path(path) => path.clone(),
+            Expr::Field(field) => match &self.expr_arena[field.expr].inner {
+                Expr::Path(alias) => alias.iter().chain([&field.field]).cloned().collect(),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let mut resolver = Resolver::new(&self.modules, &self.expr_arena);
+        let fn_id = match resolver.resolve_fn(&self.module_id, &path) {
+            Some(fn_id) => fn_id,
+            // not a function, it's checked like any other expression
+            None => return Ok(None),
+        };
+        let (resolutions, _) = self.check(None)?;
+        let mut checker = TypeChecker::new(&self.modules, &self.expr_arena, &resolutions);
+        Ok(Some(checker.signature(&fn_id)))
+    }
+
+    fn lower(&self, expr: ExprIdx) -> Result<AwlycValue, Vec<Diagnostic>> {
+        let (resolutions, _) = self.check(Some(expr))?;
+        lower_expr(
+            expr,
+            &self.modules,
+            &self.expr_arena,
+            &resolutions,
+            Limits::default(),
+        )
+        .map(|value| value.value)
+    }
+}
+
+/// Whether `src` has unclosed delimiters, in which case the input continues on the next line
+fn is_incomplete(src: &str) -> bool {
+    let depth: i32 = lex(src)
+        .map(|tok| match tok.kind {
+            TokenKind::LParen | TokenKind::LCurly | TokenKind::LSquare => 1,
+            TokenKind::RParen | TokenKind::RCurly | TokenKind::RSquare => -1,
+            _ => 0,
+        })
+        .sum();
+    depth > 0
+}
+
+/// Run an interactive session on stdin/stdout
+pub fn run() -> io::Result<()> {
+    let mut session = Session::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut stdout = io::stdout();
+
+    loop {
+        write!(stdout, "awlyc> ")?;
+        stdout.flush()?;
+        let mut input = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        while is_incomplete(&input) {
+            write!(stdout, "  ...> ")?;
+            stdout.flush()?;
+            match lines.next() {
+                Some(line) => {
+                    input.push('\n');
+                    input.push_str(&line?);
+                }
+                None => break,
+            }
+        }
+
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+
+        let (command, arg) = input.split_once(' ').unwrap_or((input, ""));
+        let arg = arg.trim();
+        match command {
+            ":quit" | ":q" => break,
+            ":help" | ":h" => println!("{}", HELP),
+            ":load" | ":l" => match session.load(arg) {
+                Ok(()) => println!("loaded `{}`", Path::new(arg).display()),
+                Err(errors) => session.report(&errors),
+            },
+            ":reload" | ":r" => match session.reload() {
+                Ok(()) => println!("reloaded"),
+                Err(errors) => session.report(&errors),
+            },
+            ":type" | ":t" => match session.type_of(arg) {
+                Ok(ty) => println!("{}", ty),
+                Err(errors) => session.report(&errors),
+            },
+            _ if command.starts_with(':') => {
+                println!("unknown command `{}`, try `:help`", command)
+            }
+            _ => match session.eval(input) {
+                Ok(Some(value)) => println!("{:#}", value),
+                Ok(None) => {}
+                Err(errors) => session.report(&errors),
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use awlyc_values::AwlycValue;
+
+    use super::Session;
+
+    #[test]
+    fn declarations_persist_between_inputs() {
+        let mut session = Session::new();
+        assert!(session.eval("fn double(x) { x * 2 }").unwrap().is_none());
+        let value = session.eval("double(21)").unwrap().unwrap();
+        assert!(matches!(value, AwlycValue::Int(42)));
+    }
+
+    #[test]
+    fn declarations_with_errors_are_rolled_back() {
+        let mut session = Session::new();
+        session.eval("fn f(x) { x }").unwrap();
+        assert!(session.eval("fn f(x) { y }").is_err());
+        let value = session.eval("f(1)").unwrap().unwrap();
+        assert!(matches!(value, AwlycValue::Int(1)));
+    }
+
+    #[test]
+    fn load_and_type() {
+        let mut session = Session::new();
+        session.load("../../examples/basic.awlyc").unwrap();
+        let ty = session.type_of(r#"topic(1, "parsing", 2.5)"#).unwrap();
+        assert_eq!(ty, "{ description: float, id: int, name: string }");
+
+        session.eval("fn topic(id) { id }").unwrap();
+        session.reload().unwrap();
+        assert_eq!(session.type_of("topic(1)").unwrap(), "int");
+    }
+
+    #[test]
+    fn failed_load_keeps_the_session() {
+        let mut session = Session::new();
+        session.load("../../examples/basic.awlyc").unwrap();
+        session.eval("fn two(): 2").unwrap();
+        let errors = session.load("../../examples/missing.awlyc").unwrap_err();
+        assert!(errors[0].msg.starts_with("could not open file"));
+        let value = session.eval("two()").unwrap().unwrap();
+        assert!(matches!(value, AwlycValue::Int(2)));
+
+        // the file that loaded last is the one reloaded
+        session.reload().unwrap();
+        assert!(session.type_of(r#"topic(1, "parsing", 2.5)"#).is_ok());
+        assert!(matches!(
+            session.eval("two()").unwrap().unwrap(),
+            AwlycValue::Int(2)
+        ));
+    }
+
+    #[test]
+    fn signatures() {
+        let mut session = Session::new();
+        session
+            .eval("fn project(title: string, stars: int) -> { title: string, stars: float } { { title: title, stars: stars } }")
+            .unwrap();
+        assert_eq!(
+            session.type_of("project").unwrap(),
+            "fn project(title: string, stars: int) -> { stars: float, title: string }"
+        );
+        session.eval("fn double(x) { x * 2.0 }").unwrap();
+        assert_eq!(
+            session.type_of("double").unwrap(),
+            "fn double(x: any) -> float"
+        );
+        assert!(session.eval(r#"project("awlyc", "many")"#).is_err());
+    }
+}
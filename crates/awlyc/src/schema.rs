@@ -0,0 +1,377 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    rc::Rc,
+    sync::Arc,
+};
+
+use awlyc_hir::ty::Ty;
+use awlyc_values::AwlycValue;
+use smol_str::SmolStr;
+
+/// Rust types whose shape can be described as an awlyc type
+/// Values are validated against the schema of the type they're deserialized into, so that every
+/// mismatch is reported with the place in the source it comes from
+/// Structs implement this with `#[derive(awlyc::Schema)]`
+pub trait Schema {
+    fn schema() -> Ty;
+}
+
+thread_local! {
+    /// Types whose schema is being built, a type inside its own schema is `Ty::Recursive`
+    static EXPANDING: RefCell<Vec<&'static str>> = const { RefCell::new(vec![]) };
+}
+
+/// Part of a struct's schema, used by the derive macro
+#[doc(hidden)]
+pub enum SchemaField {
+    /// Field with this key, it can be left out if it has a default value
+    Field(&'static str, Ty, bool),
+    /// Fields of a `#[serde(flatten)]` field, a type without fields of its own, e.g. a map,
+    /// takes the fields the struct doesn't list
+    Flatten(Ty),
+}
+
+/// Schema of a struct, used by the derive macro
+/// It accepts fields it doesn't list like serde does, unless `deny_unknown_fields` is set
+#[doc(hidden)]
+pub fn named<T: ?Sized>(
+    name: &str,
+    deny_unknown_fields: bool,
+    fields: impl FnOnce() -> Vec<SchemaField>,
+) -> Ty {
+    let id = std::any::type_name::<T>();
+    if EXPANDING.with(|expanding| expanding.borrow().contains(&id)) {
+        return Ty::Recursive {
+            name: SmolStr::from(name),
+            id: SmolStr::from(id),
+        };
+    }
+    EXPANDING.with(|expanding| expanding.borrow_mut().push(id));
+    let mut open = !deny_unknown_fields;
+    let mut keys = BTreeMap::new();
+    for field in fields() {
+        match field {
+            SchemaField::Field(k, ty, default) => {
+                let ty = if default { optional(ty) } else { ty };
+                keys.insert(SmolStr::from(k), ty);
+            }
+            SchemaField::Flatten(ty) if ty.is_record() => {
+                open |= matches!(ty, Ty::Schema { open: true, .. });
+                keys.extend(ty.fields().unwrap());
+            }
+            // every field of a flattened `Option` can be left out
+            SchemaField::Flatten(Ty::Optional(ty)) if ty.is_record() => {
+                open |= matches!(*ty, Ty::Schema { open: true, .. });
+                let fields = ty.fields().unwrap();
+                keys.extend(fields.into_iter().map(|(k, ty)| (k, optional(ty))));
+            }
+            SchemaField::Flatten(_) => open = true,
+        }
+    }
+    EXPANDING.with(|expanding| expanding.borrow_mut().pop());
+    Ty::Schema {
+        name: SmolStr::from(name),
+        id: SmolStr::from(id),
+        fields: keys,
+        open,
+    }
+}
+
+/// Type of a field that can be left out
+fn optional(ty: Ty) -> Ty {
+    match ty {
+        Ty::Optional(_) | Ty::Unknown => ty,
+        ty => Ty::Optional(Box::new(ty)),
+    }
+}
+
+macro_rules! impl_schema {
+    ($ty:expr => $($t:ty),*) => {
+        $(
+            impl Schema for $t {
+                fn schema() -> Ty {
+                    $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_schema!(Ty::Int => i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+impl_schema!(Ty::Float => f32, f64);
+impl_schema!(Ty::String => String, str, char, SmolStr);
+impl_schema!(Ty::Bool => bool);
+impl_schema!(Ty::Null => ());
+impl_schema!(Ty::Unknown => AwlycValue);
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema() -> Ty {
+        Ty::Optional(Box::new(T::schema()))
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema() -> Ty {
+        Ty::Array(Box::new(T::schema()))
+    }
+}
+
+impl<T: Schema> Schema for [T] {
+    fn schema() -> Ty {
+        Ty::Array(Box::new(T::schema()))
+    }
+}
+
+impl<T: Schema, const N: usize> Schema for [T; N] {
+    fn schema() -> Ty {
+        Ty::Array(Box::new(T::schema()))
+    }
+}
+
+impl<T: Schema> Schema for HashSet<T> {
+    fn schema() -> Ty {
+        Ty::Array(Box::new(T::schema()))
+    }
+}
+
+impl<T: Schema> Schema for BTreeSet<T> {
+    fn schema() -> Ty {
+        Ty::Array(Box::new(T::schema()))
+    }
+}
+
+// records with arbitrary keys have no awlyc type
+impl<K, V> Schema for HashMap<K, V> {
+    fn schema() -> Ty {
+        Ty::Unknown
+    }
+}
+
+impl<K, V> Schema for BTreeMap<K, V> {
+    fn schema() -> Ty {
+        Ty::Unknown
+    }
+}
+
+impl<T: Schema + ?Sized> Schema for Box<T> {
+    fn schema() -> Ty {
+        T::schema()
+    }
+}
+
+impl<T: Schema + ?Sized> Schema for Rc<T> {
+    fn schema() -> Ty {
+        T::schema()
+    }
+}
+
+impl<T: Schema + ?Sized> Schema for Arc<T> {
+    fn schema() -> Ty {
+        T::schema()
+    }
+}
+
+impl<T: Schema + ?Sized> Schema for &T {
+    fn schema() -> Ty {
+        T::schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use crate::{awlyc, Schema, Ty};
+
+    /// Fields of a derived schema with their types, and whether it's open
+    fn fields<T: Schema>() -> (Vec<(String, String)>, bool) {
+        match T::schema() {
+            Ty::Schema { fields, open, .. } => (
+                fields
+                    .into_iter()
+                    .map(|(k, ty)| (k.to_string(), ty.to_string()))
+                    .collect(),
+                open,
+            ),
+            ty => panic!("not a schema: {}", ty),
+        }
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, ty)| (k.to_string(), ty.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn defaults() {
+        #[derive(Deserialize, Schema)]
+        #[allow(dead_code)]
+        struct Field {
+            #[serde(default)]
+            port: i64,
+            #[serde(default = "String::new")]
+            host: String,
+            name: String,
+            tag: Option<String>,
+        }
+        #[derive(Deserialize, Default, Schema)]
+        #[serde(default)]
+        #[allow(dead_code)]
+        struct Container {
+            port: i64,
+        }
+
+        assert_eq!(
+            fields::<Field>().0,
+            pairs(&[
+                ("host", "string?"),
+                ("name", "string"),
+                ("port", "int?"),
+                ("tag", "string?"),
+            ])
+        );
+        assert_eq!(fields::<Container>().0, pairs(&[("port", "int?")]));
+    }
+
+    #[test]
+    fn rename_all() {
+        #[derive(Deserialize, Schema)]
+        #[serde(rename_all = "camelCase")]
+        #[allow(dead_code)]
+        struct Camel {
+            max_line_len: i64,
+            #[serde(rename = "kept")]
+            renamed_field: i64,
+            r#type: i64,
+        }
+        #[derive(Deserialize, Schema)]
+        #[serde(rename_all(deserialize = "SCREAMING-KEBAB-CASE"))]
+        #[allow(dead_code)]
+        struct Kebab {
+            max_line_len: i64,
+        }
+        #[derive(Deserialize, Schema)]
+        #[serde(rename_all = "PascalCase")]
+        #[allow(dead_code)]
+        struct Pascal {
+            max_line_len: i64,
+        }
+
+        assert_eq!(
+            fields::<Camel>().0,
+            pairs(&[("kept", "int"), ("maxLineLen", "int"), ("type", "int")])
+        );
+        assert_eq!(fields::<Kebab>().0, pairs(&[("MAX-LINE-LEN", "int")]));
+        assert_eq!(fields::<Pascal>().0, pairs(&[("MaxLineLen", "int")]));
+    }
+
+    #[test]
+    fn flatten() {
+        #[derive(Deserialize, Schema)]
+        #[allow(dead_code)]
+        struct Common {
+            name: String,
+        }
+        #[derive(Deserialize, Schema)]
+        #[serde(deny_unknown_fields)]
+        #[allow(dead_code)]
+        struct Inner {
+            draft: bool,
+        }
+        #[derive(Deserialize, Schema)]
+        #[serde(deny_unknown_fields)]
+        #[allow(dead_code)]
+        struct Page {
+            #[serde(flatten)]
+            common: Common,
+            #[serde(flatten)]
+            inner: Option<Inner>,
+            title: String,
+        }
+        #[derive(Deserialize, Schema)]
+        #[serde(deny_unknown_fields)]
+        #[allow(dead_code)]
+        struct Extra {
+            title: String,
+            #[serde(flatten)]
+            rest: HashMap<String, String>,
+        }
+
+        // `Common` accepts unknown fields, so `Page` does too
+        assert_eq!(
+            fields::<Page>(),
+            (
+                pairs(&[("draft", "bool?"), ("name", "string"), ("title", "string")]),
+                true
+            )
+        );
+        assert_eq!(fields::<Extra>(), (pairs(&[("title", "string")]), true));
+    }
+
+    #[test]
+    fn unknown_fields() {
+        #[derive(Deserialize, Schema)]
+        #[allow(dead_code)]
+        struct Open {
+            title: String,
+        }
+        #[derive(Deserialize, Schema)]
+        #[serde(deny_unknown_fields)]
+        #[allow(dead_code)]
+        struct Closed {
+            title: String,
+        }
+
+        assert!(fields::<Open>().1);
+        assert!(!fields::<Closed>().1);
+    }
+
+    #[test]
+    fn deserialize_with() {
+        fn any<'de, D: serde::Deserializer<'de>>(_: D) -> Result<i64, D::Error> {
+            Ok(0)
+        }
+        #[derive(Deserialize, Schema)]
+        #[allow(dead_code)]
+        struct Page {
+            #[serde(deserialize_with = "any")]
+            count: i64,
+        }
+
+        assert_eq!(fields::<Page>().0, pairs(&[("count", "any")]));
+    }
+
+    #[test]
+    fn recursive() {
+        #[derive(Deserialize, Schema)]
+        #[allow(dead_code)]
+        struct Tree {
+            name: String,
+            children: Vec<Tree>,
+            parent: Option<Box<Tree>>,
+        }
+
+        let value = awlyc!({
+            name: "root",
+            children: [{ name: "a", children: [{ name: 1, children: [] }] }],
+            parent: { name: "up", children: [], parent: { children: [] } },
+        });
+        let mismatches: Vec<_> = value
+            .mismatches(&Tree::schema())
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            mismatches,
+            [
+                "mismatched types at `children[0].children[0].name`: expected `string`, found `int`",
+                "missing field `parent.parent.name` in `Tree`",
+            ]
+        );
+    }
+}
@@ -0,0 +1,31 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use awlyc_error::FileId;
+use awlyc_hir::{resolve, ty};
+use awlyc_parser::parse;
+use awlyc_values::{lower_expr, Limits};
+use la_arena::Arena;
+use libfuzzer_sys::fuzz_target;
+use smol_str::SmolStr;
+
+// every stage has to return diagnostics instead of panicking, later stages only run on input the
+// earlier ones accepted, the same way the loader runs them
+fuzz_target!(|src: &str| {
+    let file_id = FileId(SmolStr::from("main"));
+    let mut expr_arena = Arena::default();
+    let (module, errors) = parse(src, &mut expr_arena, file_id.clone());
+    let expr = match module.expr {
+        Some(expr) if errors.is_empty() => expr,
+        _ => return,
+    };
+    let modules = HashMap::from([(file_id, module)]);
+    let (resolutions, errors) = resolve(&modules, &expr_arena);
+    if !errors.is_empty() {
+        return;
+    }
+    // lowering reports type errors itself, so it runs even if checking fails
+    let _ = ty::check(&modules, &expr_arena, &resolutions);
+    let _ = lower_expr(expr, &modules, &expr_arena, &resolutions, Limits::default());
+});